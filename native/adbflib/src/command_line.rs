@@ -1,6 +1,16 @@
 //! Command line modules: has one function which takes input parameters from commandline
 //! and parses them.
-use adbfbinlib::common::config;
+use adbfbinlib::{
+    common::config,
+    data::{
+        classify::MimeMapping,
+        filter::ScanRules,
+        metric::{KeyMetric, Similarity},
+        normalize::KeyNormalization,
+        options::ScanOptions,
+    },
+};
+use std::collections::HashMap;
 
 static APP_TITLE: &str = concat!("The audiobook finder (", env!("CARGO_PKG_NAME"), ")");
 
@@ -9,6 +19,27 @@ static ARG_WEBUI: &str = "webui";
 static ARG_KEEP_ALIVE: &str = "keep";
 static ARG_BROWSER: &str = "browser";
 static ARG_BROWSER_PORT: &str = "port";
+static ARG_HASH: &str = "hash";
+static ARG_SKIP_SYMLINKS: &str = "skip-symlinks";
+static ARG_ARCHIVES: &str = "archives";
+static ARG_FAST: &str = "fast";
+static ARG_AUDIOBOOKS_ONLY: &str = "audiobooks-only";
+static ARG_MAP_EXTENSION: &str = "map-extension";
+static ARG_MAP_READERS: &str = "map-readers";
+static ARG_INCLUDE: &str = "include";
+static ARG_EXCLUDE: &str = "exclude";
+static ARG_INCLUDE_IN: &str = "include-in";
+static ARG_EXCLUDE_IN: &str = "exclude-in";
+static ARG_RAW_KEYS: &str = "raw-keys";
+static ARG_KEEP_UNICODE: &str = "keep-unicode";
+static ARG_KEEP_ACCENTS: &str = "keep-accents";
+static ARG_KEEP_CASE: &str = "keep-case";
+static ARG_KEEP_NUMBERING: &str = "keep-numbering";
+static ARG_STRIP_PARTS: &str = "strip-parts";
+static ARG_KEEP_PUNCTUATION: &str = "keep-punctuation";
+static ARG_KEEP_WHITESPACE: &str = "keep-whitespace";
+static ARG_METRIC: &str = "metric";
+static ARG_TOLERANCE: &str = "tolerance";
 
 static INPUT_FOLDERS: &str = "folders";
 
@@ -25,7 +56,8 @@ const DESCRIPTION: &'static str = env!("CARGO_PKG_DESCRIPTION");
 /// open_browser,
 /// web_port,
 /// has_ui,
-pub fn get_start_values() -> (Vec<String>, bool, bool, bool, bool, u16, bool) {
+/// scan_options,
+pub fn get_start_values() -> (Vec<String>, bool, bool, bool, bool, u16, bool, ScanOptions) {
     let parse_args = clap::App::new(APP_TITLE)
         .version(config::net::VERSION)
         .author(AUTHORS)
@@ -102,6 +134,185 @@ pub fn get_start_values() -> (Vec<String>, bool, bool, bool, bool, u16, bool) {
                 .help("Shall browser not be openend automatically (only works with webui).")
                 .takes_value(false),
         )
+        .arg(
+            clap::Arg::with_name(ARG_HASH)
+                .long(ARG_HASH)
+                .help(
+                    "Hash the audio content (without tags) to find identical audio, \
+                     even if tagged differently. Reads every file completely!",
+                )
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_SKIP_SYMLINKS)
+                .long(ARG_SKIP_SYMLINKS)
+                .help("Do not follow symbolic links to files and folders.")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_ARCHIVES)
+                .long(ARG_ARCHIVES)
+                .help(
+                    "Look into ZIP archives and analyze the audio files inside. \
+                     Each audio file is read into memory completely!",
+                )
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_FAST)
+                .long(ARG_FAST)
+                .help(
+                    "Classify files by their extension, without opening them. \
+                     Only files with unknown extensions are looked into.",
+                )
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_AUDIOBOOKS_ONLY)
+                .long(ARG_AUDIOBOOKS_ONLY)
+                .help(
+                    "Only index audiobooks. Books told by genre, length and names \
+                     to be music or podcasts are left out.",
+                )
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_MAP_EXTENSION)
+                .long(ARG_MAP_EXTENSION)
+                .value_name("EXT=MIME")
+                .help(
+                    "Classify files with the extension as the mime type in fast mode \
+                     (can be given multiple times), e.g. 'm4r=audio/x-m4a'.",
+                )
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_MAP_READERS)
+                .long(ARG_MAP_READERS)
+                .value_name("SUFFIX=READERS")
+                .help(
+                    "Try these tag readers first (mp4, flac, ogg, id3, mp3) for the mime type \
+                     suffix (can be given multiple times), e.g. 'x-wav=id3'. \
+                     If they fail, all other readers are tried.",
+                )
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_INCLUDE)
+                .long(ARG_INCLUDE)
+                .value_name("GLOB")
+                .help(
+                    "Only search files matching the glob (can be given multiple times), \
+                     e.g. '*.mp3'. A glob with '/' matches paths relative to the input folder.",
+                )
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_EXCLUDE)
+                .long(ARG_EXCLUDE)
+                .value_name("GLOB")
+                .help(
+                    "Do not search files and folders matching the glob (can be given multiple \
+                     times), e.g. '@eaDir'. Per folder use an .adbfignore file (gitignore syntax).",
+                )
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_INCLUDE_IN)
+                .long(ARG_INCLUDE_IN)
+                .value_name("PATH=GLOB")
+                .help(
+                    "Like include, but only for the input folder PATH (can be given multiple \
+                     times), e.g. '/media/books=*.m4b'.",
+                )
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_EXCLUDE_IN)
+                .long(ARG_EXCLUDE_IN)
+                .value_name("PATH=GLOB")
+                .help(
+                    "Like exclude, but only for the input folder PATH (can be given multiple \
+                     times), e.g. '/media/books=podcasts/*'.",
+                )
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_RAW_KEYS)
+                .long(ARG_RAW_KEYS)
+                .help(
+                    "Compare artist and title as they are, without normalizing case, accents, \
+                     numbering, punctuation and whitespace. Overrides the single steps below.",
+                )
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_KEEP_UNICODE)
+                .long(ARG_KEEP_UNICODE)
+                .help("Do not unify unicode forms (e.g. ligatures) of artist and title.")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_KEEP_ACCENTS)
+                .long(ARG_KEEP_ACCENTS)
+                .help("Do not drop accents of artist and title.")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_KEEP_CASE)
+                .long(ARG_KEEP_CASE)
+                .help("Compare artist and title case sensitive.")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_KEEP_NUMBERING)
+                .long(ARG_KEEP_NUMBERING)
+                .help("Do not drop leading track numbers of titles, e.g. '01 - '.")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_STRIP_PARTS)
+                .long(ARG_STRIP_PARTS)
+                .help(
+                    "Drop part numbers of titles, e.g. 'Part 2' or 'CD 1'. The parts of a book \
+                     are then reported as duplicates.",
+                )
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_KEEP_PUNCTUATION)
+                .long(ARG_KEEP_PUNCTUATION)
+                .help("Do not drop the punctuation of artist and title.")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_KEEP_WHITESPACE)
+                .long(ARG_KEEP_WHITESPACE)
+                .help("Do not trim artist and title, nor collapse their spaces.")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_METRIC)
+                .long(ARG_METRIC)
+                .help(
+                    "How artist and title of books are compared: osa (default), \
+                     token-set (word order does not matter) or normalized (relative to length).",
+                )
+                .possible_values(&["osa", "token-set", "normalized"])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_TOLERANCE)
+                .long(ARG_TOLERANCE)
+                .help("Part of artist and title that may differ for similar books (default 0.15).")
+                .takes_value(true),
+        )
         .arg(
             clap::Arg::with_name(INPUT_FOLDERS)
                 .help(
@@ -127,6 +338,12 @@ pub fn get_start_values() -> (Vec<String>, bool, bool, bool, bool, u16, bool) {
     // check argments if tui and net search is needed
     //
     let has_arg = |x: &str| parse_args.is_present(x);
+    let values = |x: &str| -> Vec<String> {
+        parse_args
+            .values_of(x)
+            .map(|values| values.map(|value| value.to_string()).collect())
+            .unwrap_or_default()
+    };
 
     let has_webui = has_arg(ARG_WEBUI);
     let has_net = has_arg(ARG_NET);
@@ -197,6 +414,78 @@ pub fn get_start_values() -> (Vec<String>, bool, bool, bool, bool, u16, bool) {
     // either one will have a ui, representing data and error messages (only webui, before there was tui)
     let has_ui = has_webui;
 
+    let mut scan_options = ScanOptions {
+        hash_audio: has_arg(ARG_HASH),
+        skip_symlinks: has_arg(ARG_SKIP_SYMLINKS),
+        scan_archives: has_arg(ARG_ARCHIVES),
+        fast_classify: has_arg(ARG_FAST),
+        audiobooks_only: has_arg(ARG_AUDIOBOOKS_ONLY),
+        mime_mapping: {
+            let mut mime_mapping = MimeMapping::default();
+            for extension in values(ARG_MAP_EXTENSION) {
+                mime_mapping
+                    .map_extension(&extension)
+                    .unwrap_or_else(|e| println!("Ignoring extension mapping: {}!", e));
+            }
+            for readers in values(ARG_MAP_READERS) {
+                mime_mapping
+                    .map_readers(&readers)
+                    .unwrap_or_else(|e| println!("Ignoring reader mapping: {}!", e));
+            }
+            mime_mapping
+        },
+        rules: ScanRules {
+            include: values(ARG_INCLUDE),
+            exclude: values(ARG_EXCLUDE),
+        },
+        path_rules: HashMap::new(),
+        key_normalization: if has_arg(ARG_RAW_KEYS) {
+            KeyNormalization::none()
+        } else {
+            KeyNormalization {
+                unicode: !has_arg(ARG_KEEP_UNICODE),
+                transliterate: !has_arg(ARG_KEEP_ACCENTS),
+                case_fold: !has_arg(ARG_KEEP_CASE),
+                strip_numbering: !has_arg(ARG_KEEP_NUMBERING),
+                strip_parts: has_arg(ARG_STRIP_PARTS),
+                strip_punctuation: !has_arg(ARG_KEEP_PUNCTUATION),
+                collapse_whitespace: !has_arg(ARG_KEEP_WHITESPACE),
+            }
+        },
+        similarity: {
+            let default_similarity = Similarity::default();
+            Similarity {
+                metric: parse_args
+                    .value_of(ARG_METRIC)
+                    .and_then(KeyMetric::from_name)
+                    .unwrap_or(default_similarity.metric),
+                tolerance: parse_args
+                    .value_of(ARG_TOLERANCE)
+                    .and_then(|tolerance| tolerance.parse::<f64>().ok())
+                    .filter(|tolerance| (0.0..=1.0).contains(tolerance))
+                    .unwrap_or_else(|| {
+                        if has_arg(ARG_TOLERANCE) {
+                            println!(
+                                "Invalid tolerance, using default {}!",
+                                default_similarity.tolerance
+                            );
+                        }
+                        default_similarity.tolerance
+                    }),
+            }
+        },
+    };
+    for assignment in values(ARG_INCLUDE_IN) {
+        scan_options
+            .include_in(&assignment)
+            .unwrap_or_else(|e| println!("Ignoring include: {}!", e));
+    }
+    for assignment in values(ARG_EXCLUDE_IN) {
+        scan_options
+            .exclude_in(&assignment)
+            .unwrap_or_else(|e| println!("Ignoring exclude: {}!", e));
+    }
+
     // 1) convert to strings
     let unchecked_strings = all_pathes.iter().map(|s| s.to_string()).collect();
     (
@@ -207,5 +496,6 @@ pub fn get_start_values() -> (Vec<String>, bool, bool, bool, bool, u16, bool) {
         open_browser,
         web_port,
         has_ui,
+        scan_options,
     )
 }
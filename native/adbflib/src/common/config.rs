@@ -128,8 +128,20 @@ pub mod webui {
 
 /// The data related configurations of a more general purpose
 pub mod data {
-    /// ignore those tree_magic extensions. like m3u
-    pub static IGNORE_AUDIO_FORMATS: [&str; 1] = ["x-mpegurl"];
+    /// tree_magic extensions of playlists, like m3u
+    pub static PLAYLIST_FORMATS: [&str; 3] = ["x-mpegurl", "mpegurl", "vnd.apple.mpegurl"];
+    /// tree_magic extensions of archives that are looked into, if chosen
+    pub static ARCHIVE_FORMATS: [&str; 2] = ["zip", "x-zip-compressed"];
     /// max of input paths/folders to be used by program
     pub static PATHS_MAX: usize = 8;
+    /// folder inside the user's cache directory
+    pub static CACHE_DIR: &str = "audiobookfinder";
+    /// file name of the persistent collection cache
+    pub static CACHE_FILE: &str = "collection.cache";
+    /// has to be increased whenever the cached data layout or its analysis changes
+    pub static CACHE_VERSION: u32 = 16;
+    /// files in gitignore syntax, telling what not to search
+    pub static IGNORE_FILE: &str = ".adbfignore";
+    /// marks a folder without media (as on android)
+    pub static NO_MEDIA_FILE: &str = ".nomedia";
 }
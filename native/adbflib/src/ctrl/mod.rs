@@ -6,7 +6,7 @@ mod webui;
 use self::webui::WebUI;
 use super::{
     common::{config, paths::SearchPath},
-    data::ipc::IFCollectionOutputData,
+    data::{ipc::IFCollectionOutputData, report::DuplicateReport},
     net::subs::peer_representation::PeerRepresentation,
 };
 use async_std::task;
@@ -62,6 +62,7 @@ pub enum InternalUiMsg {
     StartAnimate(CollectionPathAlive, Status),
     StepAndAnimate(CollectionPathAlive),
     PeerSearchFinished(PeerId, IFCollectionOutputData),
+    DuplicatesFound(DuplicateReport),
     Terminate,
 }
 
@@ -71,6 +72,7 @@ pub enum UiUpdateMsg {
     NetUpdate(ForwardNetMsg),
     CollectionUpdate(CollectionPathAlive, Status),
     PeerSearchFinished(PeerId, IFCollectionOutputData),
+    DuplicatesFound(DuplicateReport),
     StopUI,
 }
 
@@ -358,6 +360,16 @@ impl Ctrl {
                     }
                     true
                 }
+                UiUpdateMsg::DuplicatesFound(report) => {
+                    for forward_sender in multiplex_send {
+                        forward_sender
+                            .send(InternalUiMsg::DuplicatesFound(report.clone()))
+                            .unwrap_or_else(|_| {
+                                warn!("forwarding message cancelled probably due to quitting!");
+                            });
+                    }
+                    true
+                }
                 UiUpdateMsg::StopUI => {
                     // if error something or Ok(false) results in the same
                     trace!("stop all message forwarding to ui");
@@ -51,6 +51,10 @@ function APPStart() {
                onRESTDir(data);
             });
 
+            ws.bind('duplicates', function(data){
+               showDuplicates(data);
+            });
+
             window.onbeforeunload = function(event) {
                socket.close();
             };
@@ -373,6 +377,53 @@ function html_build_finished_peer(data) {
     return html;
 }
 
+function showDuplicates(data) {
+    // keys, folders, paths and tags come from the files, so they are only ever set as text
+    let rows = $('#duplicates_found tbody').empty();
+    for (let i = 0; i < data.clusters.length; i++) {
+        let cluster = data.clusters[i];
+        let matching = (cluster.matching === 'Exact') ? 'same'
+                     : (cluster.matching === 'Edition') ? 'edition variant' : 'similar';
+        let cell = $('<td class="col-xs-3"></td>')
+            .append($('<b></b>').text(matching + ':'))
+            .append(document.createTextNode(' ' + cluster.key))
+            .append($('<br/>'));
+        for (let j = 0; j < cluster.books.length; j++) {
+            let book = cluster.books[j];
+            let files = book.files
+                .map(file => file.path + ' (' + file.duration.secs + 's)')
+                .join(', ');
+            // the best copy comes first, edition variants are all kept
+            if (j === 0 && cluster.matching !== 'Edition') {
+                cell.append($('<b></b>').text('keep')).append(document.createTextNode(' '));
+            }
+            if (book.cover) {
+                cell.append($('<img height="32" alt=""/>').attr('src', '/cover/' + encodeURIComponent(book.cover)))
+                    .append(document.createTextNode(' '));
+            }
+            let text = book.folder + ' (' + (book.album || 'no album') + ', '
+                     + (book.narrator ? 'read by ' + book.narrator + ', ' : '')
+                     + book.files.length + ' files, ' + book.duration.secs + 's'
+                     + (book.quality ? ', ' + helper_quality(book.quality) : '')
+                     + ((book.same_chapters === false) ? ', other chapters' : '')
+                     + ((book.same_cover === false) ? ', other cover' : '')
+                     + ((book.kind && book.kind !== 'AudioBook') ? ', ' + book.kind.toLowerCase() : '')
+                     + ')';
+            // without data-html the tooltip shows its title as text
+            cell.append($('<span class="html_tooltip" data-toggle="tooltip"></span>').attr('title', files).text(text))
+                .append($('<br/>'));
+        }
+        rows.append($('<tr></tr>').append(cell));
+    }
+}
+
+function helper_quality(quality) {
+    let variable = (quality.variable === true) ? ' VBR' : (quality.variable === false) ? ' CBR' : '';
+    return quality.codec.toLowerCase() + ' ' + quality.bitrate + 'kbit/s' + variable + ' '
+         + quality.sample_rate + 'Hz ' + quality.channels + 'ch '
+         + (quality.size / (1024 * 1024)).toFixed(1) + 'MB';
+}
+
 function helper_extractLastDir(dir_path) {
     // should be platform independent, but's I can't check all,
     // especially windows first "canonical" form is difficult
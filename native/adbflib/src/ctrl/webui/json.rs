@@ -3,7 +3,7 @@
 use super::{
     super::super::{
         ctrl::{ForwardNetMsg, NetInfoMsg, Status},
-        data::ipc::IFCollectionOutputData,
+        data::{ipc::IFCollectionOutputData, report::DuplicateReport},
         net::subs::peer_representation,
     },
     CollectionPathAlive, InternalUiMsg,
@@ -57,6 +57,7 @@ pub fn convert_internal_message(internal_msg: &InternalUiMsg) -> Result<WSJsonOu
                 data: data.clone(),
             })))
         }
+        InternalUiMsg::DuplicatesFound(report) => Ok(WSJsonOut::duplicates(report.clone())),
         InternalUiMsg::Terminate => Err("terminate is not really of interest, is it?".to_string()),
     }
 }
@@ -166,6 +167,7 @@ pub enum WSJsonOut {
     update(NetData),
     rest_dirs(DirOut),
     init_paths(Vec<String>),
+    duplicates(DuplicateReport),
     nothing(),
 }
 
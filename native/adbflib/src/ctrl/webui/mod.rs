@@ -152,6 +152,9 @@ impl WebUI {
                         .service(
                             web::resource("/js/{name}").route(web::get().to(pages::bootstrap_js)),
                         )
+                        .service(
+                            web::resource("/cover/{id}").route(web::get().to(pages::cover_image)),
+                        )
                         .service(web::resource("/ws").route(web::get().to(WebUI::websocket_answer)))
                 })),
                 |web_server_binding_chain: Result<HttpServer<_, _, _, _>, io::Error>, ipaddr| {
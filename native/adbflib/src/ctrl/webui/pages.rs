@@ -1,7 +1,9 @@
 ///! Mainly pages to be delivered and triggered from webui.
 ///! There are some static pages and few dynamical pages.
 use super::{
-    super::super::{common::config, net::subs::peer_representation::PeerRepresentation},
+    super::super::{
+        common::config, data::cover, net::subs::peer_representation::PeerRepresentation,
+    },
     WebServerState,
 };
 use actix_web::{
@@ -61,6 +63,24 @@ pub async fn bootstrap_css(path: web::Path<String>) -> impl Responder {
     }
 }
 
+/// The image of a cover by its id, only covers of collected books are known.
+pub async fn cover_image(path: web::Path<String>) -> impl Responder {
+    let id = &*path.0;
+    match cover::known(id).map(|known| known.image()) {
+        Some(Ok(image)) => HttpResponse::build(StatusCode::OK)
+            .content_type(cover::mime_type(&image))
+            .body(image),
+        Some(Err(e)) => {
+            error!("cover {} not readable: {}", id, e);
+            HttpResponse::build(StatusCode::NOT_FOUND).finish()
+        }
+        None => {
+            error!("cover: not found {}", id);
+            HttpResponse::build(StatusCode::NOT_FOUND).finish()
+        }
+    }
+}
+
 pub async fn bootstrap_js(path: web::Path<String>) -> impl Responder {
     let js = &*path.0;
     let output = match js {
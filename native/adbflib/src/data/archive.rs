@@ -0,0 +1,209 @@
+//! Looks into ZIP archives, as audiobooks are often bought and kept as
+//! unextracted downloads. An entry is named `archive.zip!/inner/path`, the
+//! archive itself is kept apart (see `AudioInfo::archive`).
+use super::tag_readers::AudioSource;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Take},
+    path::Path,
+};
+use zip::{CompressionMethod, ZipArchive};
+
+/// Separates the path of the archive from the path of the entry inside.
+pub static SEPARATOR: &str = "!/";
+/// Compressed entries are read into memory, bigger ones are skipped.
+static COMPRESSED_SIZE_MAX: u64 = 512 * 1024 * 1024;
+
+/// An opened ZIP archive.
+pub struct Archive {
+    path: String,
+    zip: ZipArchive<BufReader<File>>,
+}
+
+impl Archive {
+    /// Opens the archive, only its central directory is read.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let path_name = path
+            .to_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no utf-8 path"))?;
+        let zip = ZipArchive::new(BufReader::new(File::open(path)?)).map_err(to_io_error)?;
+        Ok(Self {
+            path: path_name.to_string(),
+            zip,
+        })
+    }
+
+    /// The paths of the files inside the archive, without folders.
+    pub fn entries(&self) -> Vec<String> {
+        self.zip
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(String::from)
+            .collect()
+    }
+
+    /// The name of an entry as used for its audio info.
+    pub fn file_name(&self, entry: &str) -> String {
+        format!("{}{}{}", self.path, SEPARATOR, entry)
+    }
+
+    /// The path of the archive file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Stored entries are read right from the archive file. Compressed ones
+    /// cannot seek, so they are read into memory, unless they are too big.
+    pub fn read(&mut self, entry: &str) -> io::Result<AudioSource> {
+        let mut zip_file = self.zip.by_name(entry).map_err(to_io_error)?;
+        if zip_file.compression() == CompressionMethod::Stored {
+            let start = zip_file.data_start();
+            return StoredEntry::open(Path::new(&self.path), start, zip_file.compressed_size())
+                .map(AudioSource::Stored);
+        }
+        // the sizes are what the archive claims, they are not trusted
+        if zip_file.size() > COMPRESSED_SIZE_MAX {
+            return Err(too_big(zip_file.size()));
+        }
+        let mut data = Vec::with_capacity(zip_file.size() as usize);
+        (&mut zip_file)
+            .take(COMPRESSED_SIZE_MAX + 1)
+            .read_to_end(&mut data)?;
+        if data.len() as u64 > COMPRESSED_SIZE_MAX {
+            return Err(too_big(data.len() as u64));
+        }
+        Ok(AudioSource::Memory(Cursor::new(data)))
+    }
+}
+
+/// An uncompressed entry, a section of the archive file.
+pub struct StoredEntry {
+    data: Take<BufReader<File>>,
+    start: u64,
+    size: u64,
+}
+
+impl StoredEntry {
+    /// The section is cut off at the end of the archive file, whatever
+    /// size the archive claims.
+    fn open(archive: &Path, start: u64, size: u64) -> io::Result<Self> {
+        let mut file = File::open(archive)?;
+        let size = size.min(file.metadata()?.len().saturating_sub(start));
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            data: BufReader::new(file).take(size),
+            start,
+            size,
+        })
+    }
+}
+
+impl Read for StoredEntry {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl BufRead for StoredEntry {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.data.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.data.consume(amt)
+    }
+}
+
+impl Seek for StoredEntry {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.size - self.data.limit();
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the entry"))?
+        .min(self.size);
+        self.data
+            .get_mut()
+            .seek(SeekFrom::Start(self.start + target))?;
+        self.data.set_limit(self.size - target);
+        Ok(target)
+    }
+}
+
+fn too_big(size: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("compressed entry of {} bytes is too big to be read", size),
+    )
+}
+
+fn to_io_error(e: zip::result::ZipError) -> io::Error {
+    match e {
+        zip::result::ZipError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, io::Write};
+    use zip::{write::FileOptions, ZipWriter};
+
+    #[test]
+    fn read_entries() {
+        let path = env::temp_dir().join("adbf_archive_test.zip");
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+        writer
+            .add_directory("Animal Farm/", FileOptions::default())
+            .unwrap();
+        writer
+            .start_file("Animal Farm/01.mp3", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"ID3 and more").unwrap();
+        writer.finish().unwrap();
+
+        let mut archive = Archive::open(&path).unwrap();
+        assert_eq!(archive.entries(), vec!["Animal Farm/01.mp3".to_string()]);
+        assert_eq!(archive.path(), path.to_str().unwrap());
+        let mut data = vec![];
+        archive
+            .read("Animal Farm/01.mp3")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, b"ID3 and more");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn seek_in_stored_entries() {
+        let path = env::temp_dir().join("adbf_archive_stored_test.zip");
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("01.mp3", stored).unwrap();
+        writer.write_all(b"0123456789").unwrap();
+        writer.start_file("02.mp3", stored).unwrap();
+        writer.write_all(b"abc").unwrap();
+        writer.finish().unwrap();
+
+        let mut archive = Archive::open(&path).unwrap();
+        let mut source = archive.read("01.mp3").unwrap();
+        assert!(matches!(source, AudioSource::Stored(_)));
+        assert_eq!(source.seek(SeekFrom::End(-4)).unwrap(), 6);
+        let mut data = vec![];
+        source.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"6789");
+        assert_eq!(source.seek(SeekFrom::Current(-8)).unwrap(), 2);
+        let mut data = [0; 3];
+        source.read_exact(&mut data).unwrap();
+        assert_eq!(&data, b"234");
+        // the entry ends where the next one starts
+        assert_eq!(source.seek(SeekFrom::Start(100)).unwrap(), 10);
+        assert_eq!(source.read(&mut data).unwrap(), 0);
+        assert!(source.seek(SeekFrom::Current(-11)).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
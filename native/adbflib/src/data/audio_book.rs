@@ -0,0 +1,571 @@
+//! An audio book is the entity that is collected, compared and published,
+//! not its single tracks. Tracks are grouped into books while collecting.
+use super::{
+    audio_info::{AudioInfo, AudioInfoKey, Chapter},
+    cover::Cover,
+    kind::{self, BookKind},
+    normalize::KeyNormalization,
+    playlist::Playlist,
+    quality::{self, Quality},
+    series,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+    time::Duration,
+};
+
+/// A book (or a single disc of it) with all its tracks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AudioBook {
+    pub author: String,
+    pub title: String,
+    pub folder: String,
+    /// the search path the book was found in, the folders above it tell
+    /// nothing about the book
+    pub root: String,
+    pub disc: Option<u16>,
+    pub total_discs: Option<u16>,
+    pub duration: Duration,
+    pub tracks: Vec<AudioInfo>,
+    /// told when the book is complete
+    pub series: Option<String>,
+    pub volume: Option<String>,
+}
+
+impl AudioBook {
+    /// The key of a book is its normalized author and title.
+    pub fn key(&self, normalization: &KeyNormalization) -> AudioInfoKey {
+        AudioInfoKey::new(
+            &normalization.apply(&self.author),
+            &normalization.apply(&self.title),
+        )
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Both are volumes of a series or discs of a book, but not the same one.
+    pub fn is_other_volume(&self, other: &AudioBook) -> bool {
+        matches!((&self.volume, &other.volume), (Some(own), Some(other)) if own != other)
+            || matches!((self.disc, other.disc), (Some(own), Some(other)) if own != other)
+    }
+
+    /// The narrator of the book is the one of its first track with a narrator.
+    /// Audiobooks often keep it as composer, music has real composers.
+    pub fn narrator(&self) -> Option<&String> {
+        let first = |field: fn(&AudioInfo) -> Option<&String>| {
+            self.tracks
+                .iter()
+                .filter_map(field)
+                .find(|narrator| !narrator.trim().is_empty())
+        };
+        first(|track| track.narrator.as_ref()).or_else(|| {
+            if self.kind() == BookKind::AudioBook {
+                first(|track| track.composer.as_ref())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Both are recordings of the book, but read by different narrators.
+    pub fn is_other_edition(&self, other: &AudioBook) -> bool {
+        let simplified = |narrator: &String| {
+            narrator
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        };
+        match (self.narrator(), other.narrator()) {
+            (Some(own), Some(other)) => simplified(own) != simplified(other),
+            _ => false,
+        }
+    }
+
+    /// Whether the book is an audiobook, music or a podcast.
+    pub fn kind(&self) -> BookKind {
+        kind::of(self)
+    }
+
+    /// The cover of the book is the one of its first track with a cover.
+    pub fn cover(&self) -> Option<&Cover> {
+        self.tracks.iter().find_map(|track| track.cover.as_ref())
+    }
+
+    /// The quality of the book, if its streams tell.
+    pub fn quality(&self) -> Option<Quality> {
+        quality::of(self)
+    }
+
+    /// The chapters of the book: the chapters inside its files, or the file
+    /// itself if it has none. So a single file book with chapters can be
+    /// compared to a book with a file per chapter.
+    pub fn chapters(&self) -> Vec<Chapter> {
+        let mut offset = Duration::from_secs(0);
+        let mut chapters = vec![];
+        for track in &self.tracks {
+            match &track.chapters {
+                Some(track_chapters) => {
+                    chapters.extend(track_chapters.iter().map(|chapter| Chapter {
+                        title: chapter.title.clone(),
+                        start: offset + chapter.start,
+                        end: offset + chapter.end,
+                    }))
+                }
+                None => chapters.push(Chapter {
+                    title: track.title.clone(),
+                    start: offset,
+                    end: offset + track.duration,
+                }),
+            }
+            offset += track.duration;
+        }
+        chapters
+    }
+
+    /// The lengths of its chapters, see `chapters`.
+    pub fn chapter_lengths(&self) -> Vec<Duration> {
+        self.chapters().iter().map(Chapter::duration).collect()
+    }
+
+    /// Both books have the same number of chapters, each of about the same length.
+    pub fn has_same_chapters(&self, other: &AudioBook, tolerance: Duration) -> bool {
+        same_chapters(&self.chapter_lengths(), &other.chapter_lengths(), tolerance)
+    }
+
+    /// Adds a track, the tracks are put in order once the book is complete.
+    fn add(&mut self, track: AudioInfo) {
+        self.duration += track.duration;
+        if self.author.is_empty() {
+            self.author = track.artist.clone();
+        }
+        self.tracks.push(track);
+    }
+}
+
+/// Both lists have the same number of chapters, each of about the same length.
+pub fn same_chapters(own: &[Duration], other: &[Duration], tolerance: Duration) -> bool {
+    own.len() == other.len()
+        && own.iter().zip(other.iter()).all(|(own, other)| {
+            let (long, short) = if own > other {
+                (own, other)
+            } else {
+                (other, own)
+            };
+            *long - *short <= tolerance
+        })
+}
+
+/// Which tracks belong to the same book: a book is expected to stay inside
+/// one folder, and copies in other folders are other books (and hence can
+/// be found as duplicates). A playlist overrides that, its tracks are a book.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+struct BookGroup {
+    folder: String,
+    album: Option<String>,
+    album_artist: Option<String>,
+    disc: Option<u16>,
+    total_discs: Option<u16>,
+    playlist: Option<String>,
+}
+
+/// A track listed in a playlist.
+#[derive(Clone, Debug)]
+struct Membership {
+    group: BookGroup,
+    position: usize,
+    /// number of entries, the smaller playlist is the more specific one
+    size: usize,
+}
+
+impl BookGroup {
+    fn of(track: &AudioInfo) -> Self {
+        let folder = Path::new(&track.file_name)
+            .parent()
+            .and_then(|parent| parent.to_str())
+            .unwrap_or("")
+            .to_string();
+        match &track.album {
+            Some(album) if !album.is_empty() => Self {
+                folder,
+                album: Some(album.clone()),
+                album_artist: track.album_artist.clone(),
+                disc: track.disc,
+                total_discs: track.total_discs,
+                playlist: None,
+            },
+            // without album information the folder is all there is
+            _ => Self {
+                folder,
+                album: None,
+                album_artist: None,
+                disc: None,
+                total_discs: None,
+                playlist: None,
+            },
+        }
+    }
+
+    /// The book of a playlist, titled by the playlist until its tracks tell
+    /// their album (see `BookShelf::take_books_below`).
+    fn of_playlist(playlist: &Playlist) -> Self {
+        Self {
+            folder: playlist.folder(),
+            album: Some(playlist.title.clone()),
+            album_artist: None,
+            disc: None,
+            total_discs: None,
+            playlist: playlist.path.to_str().map(String::from),
+        }
+    }
+
+    fn new_book(&self) -> AudioBook {
+        let title = match &self.album {
+            Some(album) => album.clone(),
+            None => self
+                .folder
+                .rsplit(MAIN_SEPARATOR)
+                .next()
+                .unwrap_or("")
+                .to_string(),
+        };
+        AudioBook {
+            author: self.album_artist.clone().unwrap_or_default(),
+            title,
+            folder: self.folder.clone(),
+            root: String::new(),
+            disc: self.disc,
+            total_discs: self.total_discs,
+            duration: Duration::from_secs(0),
+            tracks: vec![],
+            series: None,
+            volume: None,
+        }
+    }
+}
+
+/// The book shelf groups incoming tracks into books until
+/// these books are complete.
+pub struct BookShelf {
+    books: HashMap<BookGroup, AudioBook>,
+    /// canonical paths of playlist entries to their book
+    members: HashMap<PathBuf, Membership>,
+    /// file names of tracks to their position in the playlist
+    positions: HashMap<String, usize>,
+}
+
+impl BookShelf {
+    pub fn new() -> Self {
+        Self {
+            books: HashMap::new(),
+            members: HashMap::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    /// The entries of the playlist become a book of their own, unless a
+    /// smaller playlist lists them too.
+    pub fn add_playlist(&mut self, playlist: &Playlist) {
+        let group = BookGroup::of_playlist(playlist);
+        let size = playlist.entries.len();
+        for (position, entry) in playlist.entries.iter().enumerate() {
+            if self
+                .members
+                .get(entry)
+                .is_none_or(|membership| membership.size > size)
+            {
+                let membership = Membership {
+                    group: group.clone(),
+                    position,
+                    size,
+                };
+                self.members.insert(entry.clone(), membership);
+            }
+        }
+    }
+
+    /// Puts a track to the book it belongs to. The canonical path of the
+    /// track (resolved by the searching thread) finds it in playlists.
+    pub fn add(&mut self, track: AudioInfo, canonical: Option<&Path>) {
+        let members = &self.members;
+        let membership = canonical.and_then(|path| members.get(path));
+        match membership {
+            Some(membership) => {
+                self.positions
+                    .insert(track.file_name.clone(), membership.position);
+                self.books
+                    .entry(membership.group.clone())
+                    .or_insert_with(|| membership.group.new_book())
+                    .add(track);
+            }
+            None => {
+                let group = BookGroup::of(&track);
+                self.books
+                    .entry(group.clone())
+                    .or_insert_with(|| group.new_book())
+                    .add(track);
+            }
+        }
+    }
+
+    /// Takes all books out which are inside the given path, they are
+    /// complete now and so their series can be told.
+    pub fn take_books_below(&mut self, root: &Path) -> Vec<AudioBook> {
+        let (below, others): (HashMap<_, _>, HashMap<_, _>) = self
+            .books
+            .drain()
+            .partition(|(group, _)| Path::new(&group.folder).starts_with(root));
+        self.books = others;
+        // playlists of other paths would keep their tracks from being taken
+        self.members
+            .retain(|_, membership| !Path::new(&membership.group.folder).starts_with(root));
+        let positions = &mut self.positions;
+        below
+            .into_iter()
+            .map(|(group, mut book)| {
+                book.root = root.to_string_lossy().into_owned();
+                // the playlist tells the order, else disc and track do
+                book.tracks.sort_by(|a, b| {
+                    (positions.get(&a.file_name), a.disc, a.track, &a.file_name).cmp(&(
+                        positions.get(&b.file_name),
+                        b.disc,
+                        b.track,
+                        &b.file_name,
+                    ))
+                });
+                for track in &book.tracks {
+                    positions.remove(&track.file_name);
+                }
+                // like any other book, a playlist is titled by its album tag
+                if group.playlist.is_some() {
+                    if let Some(album) = book
+                        .tracks
+                        .iter()
+                        .filter_map(|track| track.album.as_ref())
+                        .find(|album| !album.is_empty())
+                    {
+                        book.title = album.clone();
+                    }
+                }
+                let (series, volume) = series::of(&book);
+                book.series = series;
+                book.volume = volume;
+                book
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::quality::Codec;
+
+    fn track(file_name: &str, album: Option<&str>, track: u16) -> AudioInfo {
+        AudioInfo {
+            duration: Duration::from_secs(60),
+            album: album.map(|a| a.to_string()),
+            file_name: file_name.to_string(),
+            artist: "George Orwell".to_string(),
+            title: format!("Chapter {}", track),
+            track: Some(track),
+            ..AudioInfo::default()
+        }
+    }
+
+    #[test]
+    fn group_tracks_into_books() {
+        let mut shelf = BookShelf::new();
+        shelf.add(track("/a/Animal Farm/02.mp3", Some("Animal Farm"), 2), None);
+        shelf.add(track("/a/Animal Farm/01.mp3", Some("Animal Farm"), 1), None);
+        shelf.add(track("/b/Animal Farm/01.mp3", Some("Animal Farm"), 1), None);
+        shelf.add(track("/b/1984/01.mp3", None, 1), None);
+
+        let mut books = shelf.take_books_below(Path::new("/a"));
+        assert_eq!(books.len(), 1);
+        let book = books.pop().unwrap();
+        assert_eq!(book.file_count(), 2);
+        assert_eq!(book.duration, Duration::from_secs(120));
+        assert_eq!(book.tracks[0].track, Some(1));
+        assert_eq!(book.author, "George Orwell");
+
+        let mut books = shelf.take_books_below(Path::new("/b"));
+        books.sort_by(|a, b| a.title.cmp(&b.title));
+        assert_eq!(books.len(), 2);
+        assert_eq!(books[0].title, "1984");
+        assert_eq!(books[1].title, "Animal Farm");
+        assert!(shelf.take_books_below(Path::new("/")).is_empty());
+    }
+
+    #[test]
+    fn compare_single_file_with_multi_file_book() {
+        let mut shelf = BookShelf::new();
+        shelf.add(track("/a/Animal Farm/01.mp3", Some("Animal Farm"), 1), None);
+        shelf.add(track("/a/Animal Farm/02.mp3", Some("Animal Farm"), 2), None);
+        let mut single_file = track("/b/Animal Farm/book.m4b", Some("Animal Farm"), 1);
+        single_file.duration = Duration::from_secs(121);
+        single_file.chapters = Some(vec![
+            Chapter {
+                title: "Chapter 1".to_string(),
+                start: Duration::from_secs(0),
+                end: Duration::from_secs(61),
+            },
+            Chapter {
+                title: "Chapter 2".to_string(),
+                start: Duration::from_secs(61),
+                end: Duration::from_secs(121),
+            },
+        ]);
+        shelf.add(single_file, None);
+
+        let multi_file = shelf.take_books_below(Path::new("/a")).pop().unwrap();
+        let single_file = shelf.take_books_below(Path::new("/b")).pop().unwrap();
+        assert_eq!(single_file.chapters().len(), 2);
+        assert_eq!(multi_file.chapters()[1].start, Duration::from_secs(60));
+        assert!(multi_file.has_same_chapters(&single_file, Duration::from_secs(2)));
+        assert!(!multi_file.has_same_chapters(&single_file, Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn playlist_makes_the_book() {
+        let dir = std::env::temp_dir().join("adbf_shelf_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, b"").unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let (first, second, other) = (file("b.mp3"), file("a.mp3"), file("c.mp3"));
+        let playlist = Playlist::parse(&dir.join("Animal Farm.m3u"), "b.mp3\na.mp3\n");
+
+        let mut shelf = BookShelf::new();
+        shelf.add_playlist(&playlist);
+        // differing tags and numbering do not matter, the playlist does
+        let canonical = |name: &str| Path::new(name).canonicalize().ok();
+        shelf.add(
+            track(&second, Some("Animal Farm II"), 1),
+            canonical(&second).as_deref(),
+        );
+        shelf.add(
+            track(&first, Some("Animal Farm I"), 1),
+            canonical(&first).as_deref(),
+        );
+        shelf.add(track(&other, Some("1984"), 1), canonical(&other).as_deref());
+        let mut books = shelf.take_books_below(&dir);
+        books.sort_by(|a, b| a.title.cmp(&b.title));
+        assert_eq!(books.len(), 2);
+        // the album of the first track titles the book
+        assert_eq!(books[1].title, "Animal Farm I");
+        let order: Vec<&str> = books[1]
+            .tracks
+            .iter()
+            .map(|t| t.file_name.as_str())
+            .collect();
+        assert_eq!(order, [first.as_str(), second.as_str()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn playlist_titles_book_without_album() {
+        let dir = std::env::temp_dir().join("adbf_shelf_title_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("01.mp3");
+        std::fs::write(&path, b"").unwrap();
+        let file_name = path.to_str().unwrap();
+        let playlist = Playlist::parse(&dir.join("Animal Farm.m3u"), "01.mp3\n");
+
+        let mut shelf = BookShelf::new();
+        shelf.add_playlist(&playlist);
+        let canonical = path.canonicalize().ok();
+        shelf.add(track(file_name, None, 1), canonical.as_deref());
+        let books = shelf.take_books_below(&dir);
+        assert_eq!(books[0].title, "Animal Farm");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn volumes_of_a_series() {
+        let mut shelf = BookShelf::new();
+        shelf.add(
+            track("/b/The Expanse/03 - Abaddon's Gate/01.mp3", None, 1),
+            None,
+        );
+        shelf.add(
+            track("/b/The Expanse/04 - Cibola Burn/01.mp3", None, 1),
+            None,
+        );
+        shelf.add(
+            track("/b/Leviathan Wakes/01.mp3", Some("The Expanse #1"), 1),
+            None,
+        );
+        let mut books = shelf.take_books_below(Path::new("/b"));
+        books.sort_by(|a, b| a.folder.cmp(&b.folder));
+        let series: Vec<_> = books
+            .iter()
+            .map(|book| (book.series.as_deref(), book.volume.as_deref()))
+            .collect();
+        assert_eq!(
+            series,
+            [
+                (Some("The Expanse"), Some("1")),
+                (Some("The Expanse"), Some("3")),
+                (Some("The Expanse"), Some("4")),
+            ]
+        );
+        assert!(books[1].is_other_volume(&books[2]));
+        assert!(!books[1].is_other_volume(&books[1]));
+    }
+
+    #[test]
+    fn narrators_make_editions() {
+        let book = |folder: &str, narrator: Option<&str>| {
+            let mut shelf = BookShelf::new();
+            let mut track = track(&format!("{}/01.mp3", folder), Some("1984"), 1);
+            track.narrator = narrator.map(String::from);
+            shelf.add(track, None);
+            shelf.take_books_below(Path::new(folder)).remove(0)
+        };
+        let simon = book("/a/1984", Some("Simon  Prebble"));
+        let same = book("/b/1984", Some("simon prebble"));
+        let other = book("/c/1984", Some("Frank Muller"));
+        let unknown = book("/d/1984", None);
+        assert!(!simon.is_other_edition(&same));
+        assert!(simon.is_other_edition(&other));
+        // without narrator it could be any edition
+        assert!(!simon.is_other_edition(&unknown));
+        // the composer is the narrator of audiobooks only
+        let mut composed = book("/e/1984", None);
+        composed.tracks[0].composer = Some("Frank Muller".to_string());
+        composed.tracks[0].genre = Some("Rock".to_string());
+        assert_eq!(composed.narrator(), None);
+        composed.tracks[0].genre = Some("Audiobook".to_string());
+        assert_eq!(composed.narrator(), Some(&"Frank Muller".to_string()));
+    }
+
+    #[test]
+    fn quality_of_the_whole_book() {
+        let mut shelf = BookShelf::new();
+        let mut first = track("/a/1984/01.mp3", Some("1984"), 1);
+        let mut quality = Quality::new(Codec::Mp3, 128, Some(false), 44100, 2);
+        quality.size = 960_000;
+        first.quality = Some(quality);
+        let mut second = track("/a/1984/02.mp3", Some("1984"), 2);
+        second.duration = Duration::from_secs(180);
+        let mut quality = Quality::new(Codec::Mp3, 64, Some(true), 22050, 1);
+        quality.size = 1_440_000;
+        second.quality = Some(quality);
+        shelf.add(first, None);
+        shelf.add(second, None);
+        shelf.add(track("/a/1984/03.mp3", Some("1984"), 3), None);
+
+        let book = shelf.take_books_below(Path::new("/a")).remove(0);
+        let mut expected = Quality::new(Codec::Mp3, 80, Some(true), 22050, 1);
+        expected.size = 2_400_000;
+        // tracks of unknown quality are left out
+        assert_eq!(book.quality(), Some(expected));
+        assert_eq!(book.tracks[2].quality, None);
+    }
+}
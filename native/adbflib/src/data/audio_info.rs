@@ -1,17 +1,33 @@
 //! Wraps bktree functionality, the container and defines audio info structs to be used
-use super::bktree::{BKTree, Distance};
-use std::{boxed::Box, time::Duration, vec::Vec};
+use super::{
+    audio_book::{AudioBook, BookShelf},
+    bktree::{BKTree, Distance},
+    cover::Cover,
+    metric::KeyMetric,
+    playlist::Playlist,
+    quality::Quality,
+};
+use std::{boxed::Box, collections::HashMap, path::Path, time::Duration, vec::Vec};
 
 /// The container keeps the collection data. It currently consists of a BKTree
 /// (https://en.wikipedia.org/wiki/BK-tree), because key is a string of lexical
-/// data.
+/// data, and of a shelf where tracks wait until their books are complete.
 pub struct Container {
-    bk_tree: BKTree<AudioInfoKey, Box<AudioInfo>>,
+    bk_tree: BKTree<AudioInfoKey, Box<AudioBook>>,
+    shelf: BookShelf,
+    /// payload hash to the file it was seen first
+    payloads: HashMap<String, String>,
 }
 impl Container {
     pub fn new() -> Self {
+        Self::with_metric(KeyMetric::default())
+    }
+    /// The keys of books are compared with the given metric.
+    pub fn with_metric(metric: KeyMetric) -> Self {
         Self {
-            bk_tree: BKTree::new(),
+            bk_tree: BKTree::with_metric(metric.distance()),
+            shelf: BookShelf::new(),
+            payloads: HashMap::new(),
         }
     }
     /// wraps and protects the bktree find but with AudioInfoKey
@@ -19,18 +35,78 @@ impl Container {
         &self,
         searcher: &AudioInfoKey,
         tolerance: usize,
-    ) -> (Vec<&Box<AudioInfo>>, Vec<&AudioInfoKey>) {
+    ) -> (Vec<&Box<AudioBook>>, Vec<&AudioInfoKey>) {
         self.bk_tree.find(&searcher, tolerance)
     }
 
     /// wraps and protects the bktree insert but with AudioInfoKey
-    pub fn insert(&mut self, key: AudioInfoKey, value: Box<AudioInfo>) {
+    pub fn insert(&mut self, key: AudioInfoKey, value: Box<AudioBook>) {
         self.bk_tree.insert(key, value);
     }
 
-    pub fn flush(&self) -> Vec<(&AudioInfoKey, &Box<AudioInfo>)> {
+    /// Removes the books of the key (e.g. when their files were removed or renamed)
+    /// and forgets their payloads.
+    pub fn remove(&mut self, key: &AudioInfoKey) -> Vec<Box<AudioBook>> {
+        let removed = self.bk_tree.remove(key);
+        for audio_book in &removed {
+            self.payloads.retain(|_, file_name| {
+                !audio_book
+                    .tracks
+                    .iter()
+                    .any(|track| &track.file_name == file_name)
+            });
+        }
+        removed
+    }
+
+    /// Replaces the book of the key (or inserts it) and returns the old one.
+    pub fn update(&mut self, key: AudioInfoKey, value: Box<AudioBook>) -> Option<Box<AudioBook>> {
+        self.bk_tree.update(key, value)
+    }
+
+    /// The k books closest to the key, the closest first, with their distance.
+    pub fn nearest(
+        &self,
+        searcher: &AudioInfoKey,
+        k: usize,
+    ) -> Vec<(usize, &AudioInfoKey, &Box<AudioBook>)> {
+        self.bk_tree.nearest(searcher, k)
+    }
+
+    pub fn flush(&self) -> Vec<(&AudioInfoKey, &Box<AudioBook>)> {
         self.bk_tree.dfs()
     }
+
+    /// Puts a single track aside until its book is complete.
+    pub fn add_track(&mut self, track: AudioInfo, canonical: Option<&Path>) {
+        self.shelf.add(track, canonical);
+    }
+
+    /// The tracks of the playlist make a book, in playlist order.
+    pub fn add_playlist(&mut self, playlist: &Playlist) {
+        self.shelf.add_playlist(playlist);
+    }
+
+    /// Takes the (then complete) books of an already searched path.
+    pub fn take_books_below(&mut self, root: &Path) -> Vec<AudioBook> {
+        self.shelf.take_books_below(root)
+    }
+
+    /// The file that had the same audio payload before, if any.
+    pub fn known_payload(&self, payload_hash: &str) -> Option<&String> {
+        self.payloads.get(payload_hash)
+    }
+
+    /// Remembers the audio payloads of all tracks of the book.
+    pub fn remember_payloads(&mut self, audio_book: &AudioBook) {
+        for track in &audio_book.tracks {
+            if let Some(payload_hash) = &track.payload_hash {
+                self.payloads
+                    .entry(payload_hash.clone())
+                    .or_insert_with(|| track.file_name.clone());
+            }
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -38,12 +114,52 @@ pub struct AudioInfoKey {
     k: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The information about a single file (a track of a book).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct AudioInfo {
     pub duration: Duration,
-    pub album: String,
+    pub album: Option<String>,
     pub file_name: String,
-    // todo: more information should be used
+    /// the ZIP archive the file is an entry of, if it is
+    pub archive: Option<String>,
+    pub artist: String,
+    pub title: String,
+    pub album_artist: Option<String>,
+    pub track: Option<u16>,
+    pub disc: Option<u16>,
+    pub total_discs: Option<u16>,
+    pub chapters: Option<Vec<Chapter>>,
+    /// hash of the audio without tags, if hashing was chosen
+    pub payload_hash: Option<String>,
+    /// the CUE sheet that completed the tags, if any
+    pub cue_sheet: Option<String>,
+    pub series: Option<String>,
+    /// the volume inside the series, normalized ("03" is "3")
+    pub volume: Option<String>,
+    pub grouping: Option<String>,
+    pub narrator: Option<String>,
+    /// audiobooks keep the narrator there, if not in its own field
+    pub composer: Option<String>,
+    pub isbn: Option<String>,
+    pub description: Option<String>,
+    pub genre: Option<String>,
+    /// the embedded cover, else the one of its folder
+    pub cover: Option<Cover>,
+    pub quality: Option<Quality>,
+}
+
+/// A chapter inside a single file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+impl Chapter {
+    pub fn duration(&self) -> Duration {
+        self.end.saturating_sub(self.start)
+    }
 }
 
 /// protect handling of AudioInfoKey
@@ -23,18 +23,40 @@
 // © 2020 GitHub, Inc.
 // Terms
 // https://github.com/tempor1s/bktree-rs
-use std::{char, cmp::min, collections::HashMap};
+use std::{char, cmp::min, collections::HashMap, mem};
 
 /// BKTree structure that is used to store word like structures
 /// and perform "fuzzy" search on them to implement "do you mean"
 /// functionality on them. Can perform said search on any term that implements
 /// the distance trait. The default implementation is Osa distance.
-#[derive(Default)]
+/// Another metric can be given instead of the distance trait.
+///
+/// Removed items are only marked as removed (tombstones), because their keys
+/// still lead to their children. The tree is rebuilt once there are more
+/// removed than live items.
 pub struct BKTree<K, V>
 where
     K: Distance,
 {
     root: Option<BKTreeNode<K, V>>,
+    metric: Metric<K>,
+    /// number of live items
+    len: usize,
+    /// number of removed items still in the tree
+    tombstones: usize,
+}
+
+/// A distance between two keys, it has to be a metric (at least
+/// the triangle inequality has to hold) for the search to work.
+pub type Metric<K> = fn(&K, &K) -> usize;
+
+impl<K, V> Default for BKTree<K, V>
+where
+    K: Distance,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<K, V> BKTree<K, V>
@@ -43,7 +65,17 @@ where
 {
     /// Create a new BK Tree with an empty root.
     pub fn new() -> BKTree<K, V> {
-        BKTree { root: None }
+        Self::with_metric(K::distance)
+    }
+
+    /// Create a new BK Tree with an empty root, that uses the given metric.
+    pub fn with_metric(metric: Metric<K>) -> BKTree<K, V> {
+        BKTree {
+            root: None,
+            metric,
+            len: 0,
+            tombstones: 0,
+        }
     }
 
     /// Create a new tree from the items in a Vector.
@@ -51,7 +83,7 @@ where
     /// Vector requires a Vec of tuples of K, V pairs where K implements Distance trait
     #[allow(dead_code)]
     pub fn new_from_vec(items: Vec<(K, V)>) -> BKTree<K, V> {
-        let mut tree = BKTree { root: None };
+        let mut tree = BKTree::new();
 
         for item in items {
             tree.insert(item.0, item.1);
@@ -64,11 +96,45 @@ where
     pub fn insert(&mut self, key: K, value: V) {
         // If the root exists, insert from there.
         if let Some(root) = &mut self.root {
-            root.insert(key, value);
+            if root.insert(key, value, self.metric) {
+                self.tombstones -= 1;
+            }
         } else {
             // otherwise, set the root to be a new BKTreeNode
             self.root = Some(BKTreeNode::new(key, value));
         }
+        self.len += 1;
+    }
+
+    /// Replaces the value of the first exact match of the key and returns the
+    /// old value, or inserts the (key, value) pair if there is no match.
+    pub fn update(&mut self, key: K, value: V) -> Option<V> {
+        let metric = self.metric;
+        let old_value = self
+            .root
+            .as_mut()
+            .and_then(|root| root.get_mut(&key, metric));
+        match old_value {
+            Some(old_value) => Some(mem::replace(old_value, value)),
+            None => {
+                self.insert(key, value);
+                None
+            }
+        }
+    }
+
+    /// Removes all exact matches of the key and returns their values.
+    pub fn remove(&mut self, key: &K) -> Vec<V> {
+        let mut removed = vec![];
+        if let Some(root) = &mut self.root {
+            root.remove(key, self.metric, &mut removed);
+        }
+        self.len -= removed.len();
+        self.tombstones += removed.len();
+        if self.tombstones > self.len {
+            self.rebuild();
+        }
+        removed
     }
 
     /// Search for the closest Item to the key with a given tolerence. (Steps to get there)
@@ -79,13 +145,26 @@ where
     pub fn find(&self, key: &K, tolerence: usize) -> (Vec<&V>, Vec<&K>) {
         // if our root exists, search from the root
         return if let Some(root) = &self.root {
-            root.find(&key, tolerence)
+            root.find(&key, tolerence, self.metric)
         } else {
             // if we can not find anything, return a tuple of empty vectors
             (vec![], vec![])
         };
     }
 
+    /// The (up to) k items closest to the key, the closest first,
+    /// each with its distance to the key.
+    pub fn nearest(&self, key: &K, k: usize) -> Vec<(usize, &K, &V)> {
+        let mut best = Vec::with_capacity(k + 1);
+        if let Some(root) = &self.root {
+            if k > 0 {
+                root.nearest(key, k, self.metric, &mut best);
+            }
+        }
+        best
+    }
+
+    /// All live items, depth first.
     pub fn dfs(&self) -> Vec<(&K, &V)> {
         let mut out = vec![];
         if let Some(ref root) = self.root {
@@ -93,6 +172,19 @@ where
         }
         out
     }
+
+    /// Builds the tree again from the live items only.
+    fn rebuild(&mut self) {
+        let mut items = vec![];
+        if let Some(root) = self.root.take() {
+            root.into_items(&mut items);
+        }
+        self.len = 0;
+        self.tombstones = 0;
+        for (key, value) in items {
+            self.insert(key, value);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -101,7 +193,8 @@ where
     K: Distance,
 {
     key: K,
-    value: V,
+    /// None if the item was removed
+    value: Option<V>,
     children: HashMap<usize, BKTreeNode<K, V>>,
 }
 
@@ -113,36 +206,71 @@ where
     fn new(key: K, value: V) -> Self {
         BKTreeNode {
             key,
-            value,
+            value: Some(value),
             children: HashMap::new(),
         }
     }
 
-    /// Insert a new (key, value) pair into this nodes children
-    fn insert(&mut self, key: K, value: V) {
+    /// Insert a new (key, value) pair into this nodes children,
+    /// true if a removed node was used for it.
+    fn insert(&mut self, key: K, value: V, metric: Metric<K>) -> bool {
         // Get the distance between the current nodes key and the given key
-        let distance = self.key.distance(&key);
+        let distance = metric(&self.key, &key);
+        // A removed node with the same key can be used again, its children
+        // have the same distances to the new key
+        if distance == 0 && self.value.is_none() {
+            self.key = key;
+            self.value = Some(value);
+            return true;
+        }
         // If the child exists, traverse and insert from there.
         if let Some(child) = self.children.get_mut(&distance) {
-            child.insert(key, value);
+            child.insert(key, value, metric)
         } else {
             // otherwise, insert the current node into the children and with the given distance
             self.children.insert(distance, BKTreeNode::new(key, value));
+            false
+        }
+    }
+
+    /// The value of the first exact match.
+    fn get_mut(&mut self, key: &K, metric: Metric<K>) -> Option<&mut V> {
+        let distance = metric(&self.key, key);
+        if distance == 0 && self.value.is_some() {
+            return self.value.as_mut();
+        }
+        // exact matches of the key have the same distance to this node as the key
+        self.children
+            .get_mut(&distance)
+            .and_then(|child| child.get_mut(key, metric))
+    }
+
+    /// Removes the values of all exact matches.
+    fn remove(&mut self, key: &K, metric: Metric<K>, removed: &mut Vec<V>) {
+        let distance = metric(&self.key, key);
+        if distance == 0 {
+            removed.extend(self.value.take());
+        }
+        if let Some(child) = self.children.get_mut(&distance) {
+            child.remove(key, metric, removed);
         }
     }
 
     /// Find a key in the given childrens nodes
-    fn find(&self, key: &K, leniency: usize) -> (Vec<&V>, Vec<&K>) {
+    fn find(&self, key: &K, leniency: usize, metric: Metric<K>) -> (Vec<&V>, Vec<&K>) {
         // Create a new tuple of empty vectors for exact and close matches
         let (mut exact, mut close) = (vec![], vec![]);
         // Get the distance between the current nodes key and then passed in key.
-        let current_distance = self.key.distance(&key);
-        // If the current distance is 0, it means its an exact match so push it to our "exact" matches
-        if current_distance == 0 {
-            exact.push(&self.value);
-        // Otherwise, if the value is less than our leniency then add it to the close matches
-        } else if current_distance <= leniency {
-            close.push(&self.key);
+        let current_distance = metric(&self.key, key);
+        // Removed nodes only lead the way to their children
+        if let Some(value) = &self.value {
+            // If the current distance is 0, it means its an exact match so push it to our "exact" matches
+            if current_distance == 0 {
+                exact.push(value);
+            // Otherwise, if the value is less than our leniency then add it to the close matches
+            } else if current_distance <= leniency {
+                close.push(&self.key);
+            }
         }
 
         // Saturing just means that the values will not overflow
@@ -151,7 +279,7 @@ where
         {
             // Because of how the tree works, we can traverse based off the leniency
             if let Some(child) = self.children.get(&i) {
-                let mut result = child.find(key, leniency);
+                let mut result = child.find(key, leniency, metric);
                 exact.append(&mut result.0);
                 close.append(&mut result.1);
             }
@@ -160,13 +288,54 @@ where
         return (exact, close);
     }
 
+    /// Keeps the k closest items in best, ordered by distance. The search radius
+    /// shrinks to the distance of the k-th best item once there are k.
+    fn nearest<'a>(
+        &'a self,
+        key: &K,
+        k: usize,
+        metric: Metric<K>,
+        best: &mut Vec<(usize, &'a K, &'a V)>,
+    ) {
+        let current_distance = metric(&self.key, key);
+        if let Some(value) = &self.value {
+            let position = best.partition_point(|(distance, _, _)| *distance <= current_distance);
+            if position < k {
+                best.insert(position, (current_distance, &self.key, value));
+                best.truncate(k);
+            }
+        }
+        // the closest children first, they are the most promising
+        let mut children: Vec<(&usize, &BKTreeNode<K, V>)> = self.children.iter().collect();
+        children.sort_by_key(|(i, _)| (**i as isize - current_distance as isize).unsigned_abs());
+        for (i, child) in children {
+            let radius = if best.len() < k {
+                usize::MAX
+            } else {
+                best[k - 1].0
+            };
+            if (*i as isize - current_distance as isize).unsigned_abs() <= radius {
+                child.nearest(key, k, metric, best);
+            }
+        }
+    }
+
     fn traverse<'a>(&'a self, mut out: &mut Vec<(&'a K, &'a V)>) {
-        if self.children.len() > 0 {
-            self.children
-                .iter()
-                .for_each(|(_, child)| child.traverse(&mut out));
-        } else {
-            out.push((&self.key, &self.value));
+        if let Some(value) = &self.value {
+            out.push((&self.key, value));
+        }
+        self.children
+            .iter()
+            .for_each(|(_, child)| child.traverse(&mut out));
+    }
+
+    /// Takes all live items out of the node and its children.
+    fn into_items(self, items: &mut Vec<(K, V)>) {
+        if let Some(value) = self.value {
+            items.push((self.key, value));
+        }
+        for (_, child) in self.children {
+            child.into_items(items);
         }
     }
 }
@@ -247,6 +416,30 @@ pub fn osa_distance(a: &str, b: &str) -> usize {
     current_distances[b_len]
 }
 
+/// Levenshtein distance, unlike the Osa distance a real metric.
+pub fn levenshtein_distance(a: &str, b: &str) -> usize {
+    if a == b {
+        return 0;
+    }
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut prev_distances: Vec<usize> = (0..=b_chars.len()).collect();
+    let mut current_distances = vec![0; b_chars.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current_distances[0] = i + 1;
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let cost = if a_char == *b_char { 0 } else { 1 };
+            current_distances[j + 1] = min(
+                current_distances[j] + 1,
+                min(prev_distances[j + 1] + 1, prev_distances[j] + cost),
+            );
+        }
+        prev_distances.clone_from(&current_distances);
+    }
+
+    prev_distances[b_chars.len()]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -284,4 +477,98 @@ mod tests {
         assert_eq!(14, osa_distance(TEST_DATA[5].0, TEST_DATA[5].1));
         assert_eq!(33, osa_distance(TEST_DATA[6].0, TEST_DATA[6].1));
     }
+
+    #[test]
+    fn levenshtein_1() {
+        assert_eq!(0, levenshtein_distance(TEST_DATA[0].0, TEST_DATA[0].0));
+        assert_eq!(9, levenshtein_distance(TEST_DATA[0].0, TEST_DATA[0].1));
+        assert_eq!(4, levenshtein_distance(TEST_DATA[3].0, TEST_DATA[3].1));
+        // a swap is two steps, not one
+        assert_eq!(2, levenshtein_distance("ab", "ba"));
+        assert_eq!(3, levenshtein_distance("", "abc"));
+    }
+
+    #[test]
+    fn find_with_metric() {
+        let mut tree: BKTree<&str, usize> =
+            BKTree::with_metric(|a, b| (a.len() as isize - b.len() as isize).unsigned_abs());
+        tree.insert("a", 1);
+        tree.insert("abc", 3);
+        tree.insert("abcde", 5);
+        let (exact, close) = tree.find(&"xyz", 1);
+        assert_eq!(exact, vec![&3]);
+        assert_eq!(close.len(), 0);
+    }
+
+    fn genesis_tree() -> BKTree<&'static str, usize> {
+        let mut tree = BKTree::new();
+        for (index, (original, misspelled)) in TEST_DATA.iter().enumerate() {
+            tree.insert(*original, index);
+            tree.insert(*misspelled, index + 10);
+        }
+        tree
+    }
+
+    #[test]
+    fn dfs_visits_inner_nodes() {
+        let tree = genesis_tree();
+        let mut values: Vec<usize> = tree.dfs().iter().map(|(_, value)| **value).collect();
+        values.sort_unstable();
+        assert_eq!(
+            values,
+            vec![0, 1, 2, 3, 4, 5, 6, 10, 11, 12, 13, 14, 15, 16]
+        );
+    }
+
+    #[test]
+    fn remove_and_update() {
+        let mut tree = genesis_tree();
+        assert_eq!(tree.len, 14);
+        assert_eq!(tree.remove(&TEST_DATA[0].0), vec![0]);
+        assert_eq!(tree.remove(&TEST_DATA[0].0), Vec::<usize>::new());
+        assert_eq!(tree.len, 13);
+        assert_eq!(tree.find(&TEST_DATA[0].0, 0).0.len(), 0);
+        // the children of the removed key are still found
+        assert_eq!(tree.find(&TEST_DATA[0].1, 0).0, vec![&10]);
+        assert_eq!(tree.dfs().len(), 13);
+
+        assert_eq!(tree.update(TEST_DATA[1].0, 100), Some(1));
+        assert_eq!(tree.find(&TEST_DATA[1].0, 0).0, vec![&100]);
+        assert_eq!(tree.update(TEST_DATA[0].0, 0), None);
+        assert_eq!(tree.len, 14);
+
+        // removing most of it rebuilds the tree
+        for (original, misspelled) in TEST_DATA.iter().skip(1) {
+            tree.remove(original);
+            tree.remove(misspelled);
+        }
+        assert_eq!(tree.len, 2);
+        assert_eq!(tree.tombstones, 0);
+        assert_eq!(tree.find(&TEST_DATA[0].1, 0).0, vec![&10]);
+    }
+
+    #[test]
+    fn k_nearest() {
+        let tree = genesis_tree();
+        let nearest = tree.nearest(&"Genesis - The Carpet Crawler", 3);
+        assert_eq!(nearest.len(), 3);
+        assert_eq!((nearest[0].0, nearest[0].2), (1, &4));
+        assert_eq!((nearest[1].0, nearest[1].2), (6, &14));
+        assert!(nearest[2].0 >= nearest[1].0);
+        // the same as checking all of them
+        let mut all: Vec<usize> = TEST_DATA
+            .iter()
+            .flat_map(|(a, b)| vec![*a, *b])
+            .map(|key| osa_distance(key, "Genesis - The Carpet Crawler"))
+            .collect();
+        all.sort_unstable();
+        assert_eq!(
+            nearest
+                .iter()
+                .map(|(distance, _, _)| *distance)
+                .collect::<Vec<_>>(),
+            all[..3].to_vec()
+        );
+        assert_eq!(tree.nearest(&"zehn", 0).len(), 0);
+    }
 }
@@ -0,0 +1,330 @@
+//! The cache keeps the analyzed audio data of the last run on disk, so that
+//! a rescan only needs to open and parse files whose size or modification
+//! time changed, also files which gave no track. Files which disappeared
+//! are dropped when the cache is stored.
+use super::{super::common::config, audio_info::AudioInfo};
+use bincode;
+use dirs;
+use std::{
+    collections::HashMap,
+    fs::{self, File, Metadata},
+    io::{self, BufReader, BufWriter},
+    mem,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// What reading the tags of a file found out.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Analysis {
+    Track(Box<AudioInfo>),
+    /// tags without enough information for a track
+    Incomplete,
+    /// no tag reader accepted the file
+    Rejected,
+}
+
+impl Analysis {
+    /// The track, if the file gave one.
+    pub fn track(&self) -> Option<&AudioInfo> {
+        match self {
+            Analysis::Track(audio_info) => Some(audio_info),
+            Analysis::Incomplete | Analysis::Rejected => None,
+        }
+    }
+}
+
+/// Everything known about a single analyzed file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheEntry {
+    pub size: u64,
+    pub modified: Duration,
+    /// files that completed the tags (CUE sheets, sidecars) with their size
+    /// and modification time
+    pub companions: Vec<(PathBuf, u64, Duration)>,
+    pub analysis: Analysis,
+}
+
+impl CacheEntry {
+    /// The entry of a file, if its size and modification time are known.
+    pub fn new(metadata: &Metadata, companions: &[PathBuf], analysis: Analysis) -> Option<Self> {
+        let (size, modified) = file_signature(metadata)?;
+        Some(Self {
+            size,
+            modified,
+            companions: companion_signatures(companions),
+            analysis,
+        })
+    }
+}
+
+/// The cache holds the entries read at startup (old) and the
+/// entries confirmed or newly added during the current run (new).
+pub struct CollectionCache {
+    file: Option<PathBuf>,
+    old: Arc<HashMap<String, CacheEntry>>,
+    new: HashMap<String, CacheEntry>,
+}
+
+/// Read access to the entries of the last run, shared by all searching
+/// threads without a lock.
+#[derive(Clone)]
+pub struct CacheLookup {
+    old: Arc<HashMap<String, CacheEntry>>,
+}
+
+impl CacheLookup {
+    /// Returns the cached entry if size and modification time still fit,
+    /// also those of its companions (which may have been added or removed).
+    pub fn lookup(
+        &self,
+        file_name: &str,
+        metadata: &Metadata,
+        companions: &[PathBuf],
+    ) -> Option<CacheEntry> {
+        let (size, modified) = file_signature(metadata)?;
+        let entry = self.old.get(file_name)?;
+        if entry.size == size
+            && entry.modified == modified
+            && entry.companions == companion_signatures(companions)
+        {
+            Some(entry.clone())
+        } else {
+            trace!("cache entry of {} is outdated", file_name);
+            None
+        }
+    }
+}
+
+impl CollectionCache {
+    /// An empty cache which is never written to disk.
+    pub fn new() -> Self {
+        Self {
+            file: None,
+            old: Arc::new(HashMap::new()),
+            new: HashMap::new(),
+        }
+    }
+
+    /// Reads the cache from the default cache file. A missing, unreadable
+    /// or outdated cache file simply results in an empty cache.
+    pub fn load() -> Self {
+        match default_cache_file() {
+            Some(cache_file) => Self::open(cache_file),
+            None => {
+                warn!("no cache directory available, caching is disabled!");
+                Self::new()
+            }
+        }
+    }
+
+    /// Reads the cache from the given cache file, and writes it back there.
+    fn open(cache_file: PathBuf) -> Self {
+        let old = read_cache_file(&cache_file).unwrap_or_else(|e| {
+            info!("cache file {:?} not used: {}", cache_file, e);
+            HashMap::new()
+        });
+        Self {
+            file: Some(cache_file),
+            old: Arc::new(old),
+            new: HashMap::new(),
+        }
+    }
+
+    /// The entries of the last run to look files up in.
+    pub fn lookup_handle(&self) -> CacheLookup {
+        CacheLookup {
+            old: self.old.clone(),
+        }
+    }
+
+    /// Remembers the entry of a file (unchanged or analyzed again) for the next run.
+    pub fn remember(&mut self, file_name: String, entry: CacheEntry) {
+        self.new.insert(file_name, entry);
+    }
+
+    /// Writes the cache back to disk. Old entries inside the searched paths
+    /// which were not remembered again belong to files that disappeared or
+    /// changed, and are dropped. Entries of other (not searched) paths are kept.
+    pub fn store(&mut self, searched_paths: &[String]) -> io::Result<()> {
+        let cache_file = match &self.file {
+            Some(cache_file) => cache_file.clone(),
+            None => return Ok(()),
+        };
+        let searched_paths: Vec<PathBuf> = searched_paths
+            .iter()
+            .map(|searched| canonical(Path::new(searched)))
+            .collect();
+        let old = mem::take(&mut self.old);
+        for (file_name, entry) in old.iter() {
+            let inside_searched = searched_paths
+                .iter()
+                .any(|searched| Path::new(file_name).starts_with(searched));
+            if !inside_searched {
+                self.new
+                    .entry(file_name.clone())
+                    .or_insert_with(|| entry.clone());
+            }
+        }
+        write_cache_file(&cache_file, &self.new)
+    }
+}
+
+/// The absolute path, so files are found in the cache from any working
+/// directory. Paths which do not exist (anymore) are taken as they are.
+pub fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The cache file in the user's cache directory, if there is one.
+fn default_cache_file() -> Option<PathBuf> {
+    dirs::cache_dir().map(|cache_dir| {
+        cache_dir
+            .join(config::data::CACHE_DIR)
+            .join(config::data::CACHE_FILE)
+    })
+}
+
+/// Size and modification time identify an unchanged file.
+fn file_signature(metadata: &Metadata) -> Option<(u64, Duration)> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified))
+}
+
+fn companion_signatures(companions: &[PathBuf]) -> Vec<(PathBuf, u64, Duration)> {
+    companions
+        .iter()
+        .filter_map(|companion| {
+            let (size, modified) = file_signature(&fs::metadata(companion).ok()?)?;
+            Some((companion.clone(), size, modified))
+        })
+        .collect()
+}
+
+fn read_cache_file(cache_file: &Path) -> io::Result<HashMap<String, CacheEntry>> {
+    let mut reader = BufReader::new(File::open(cache_file)?);
+    let version: u32 = bincode::deserialize_from(&mut reader)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if version != config::data::CACHE_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "version {} does not match {}",
+                version,
+                config::data::CACHE_VERSION
+            ),
+        ));
+    }
+    bincode::deserialize_from(&mut reader)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_cache_file(cache_file: &Path, entries: &HashMap<String, CacheEntry>) -> io::Result<()> {
+    if let Some(cache_dir) = cache_file.parent() {
+        fs::create_dir_all(cache_dir)?;
+    }
+    // write to a temporary file first, a broken run shall not leave a broken cache
+    let temp_file = cache_file.with_extension("tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&temp_file)?);
+        bincode::serialize_into(&mut writer, &config::data::CACHE_VERSION)
+            .and_then(|_| bincode::serialize_into(&mut writer, entries))
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    }
+    fs::rename(&temp_file, cache_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file_name: &Path) -> CacheEntry {
+        CacheEntry {
+            size: 1,
+            modified: Duration::from_secs(1),
+            companions: vec![],
+            analysis: Analysis::Track(Box::new(AudioInfo {
+                duration: Duration::from_secs(60),
+                file_name: file_name.to_str().unwrap().to_string(),
+                artist: "George Orwell".to_string(),
+                title: "1984".to_string(),
+                ..AudioInfo::default()
+            })),
+        }
+    }
+
+    fn cached_files(cache: &CollectionCache) -> Vec<String> {
+        let mut files: Vec<String> = cache.old.keys().cloned().collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn prune_searched_paths_only() {
+        let dir = std::env::temp_dir().join("adbf_cache_test");
+        fs::create_dir_all(dir.join("searched")).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let cache_file = dir.join("collection.cache");
+        let (gone, kept) = (dir.join("searched/gone.mp3"), dir.join("other/kept.mp3"));
+
+        let mut cache = CollectionCache::open(cache_file.clone());
+        for file in [&gone, &kept] {
+            cache.remember(file.to_str().unwrap().to_string(), entry(file));
+        }
+        cache.store(&[]).unwrap();
+        let mut cache = CollectionCache::open(cache_file.clone());
+        assert_eq!(cached_files(&cache).len(), 2);
+
+        // nothing was found again in the searched path, given relative here
+        let relative = dir.join("searched/../searched");
+        cache
+            .store(&[relative.to_str().unwrap().to_string()])
+            .unwrap();
+        let cache = CollectionCache::open(cache_file);
+        assert_eq!(cached_files(&cache), [kept.to_str().unwrap()]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejected_files_are_cached() {
+        let dir = std::env::temp_dir().join("adbf_cache_rejected_test");
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let cache_file = dir.join("collection.cache");
+        let rejected = dir.join("broken.mp3");
+        fs::write(&rejected, b"no tags").unwrap();
+        let metadata = fs::metadata(&rejected).unwrap();
+        let file_name = rejected.to_str().unwrap();
+
+        let mut cache = CollectionCache::open(cache_file.clone());
+        let entry = CacheEntry::new(&metadata, &[], Analysis::Rejected).unwrap();
+        cache.remember(file_name.to_string(), entry);
+        cache.store(&[]).unwrap();
+        let cache = CollectionCache::open(cache_file);
+        let cached = cache.lookup_handle().lookup(file_name, &metadata, &[]);
+        assert!(matches!(cached.unwrap().analysis, Analysis::Rejected));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn other_version_is_not_read() {
+        let dir = std::env::temp_dir().join("adbf_cache_version_test");
+        fs::create_dir_all(&dir).unwrap();
+        let cache_file = dir.join("collection.cache");
+        let mut entries = HashMap::new();
+        let file_name = dir.join("01.mp3");
+        entries.insert(file_name.to_str().unwrap().to_string(), entry(&file_name));
+        write_cache_file(&cache_file, &entries).unwrap();
+        assert_eq!(read_cache_file(&cache_file).unwrap().len(), 1);
+
+        let mut writer = BufWriter::new(File::create(&cache_file).unwrap());
+        bincode::serialize_into(&mut writer, &(config::data::CACHE_VERSION - 1)).unwrap();
+        bincode::serialize_into(&mut writer, &entries).unwrap();
+        drop(writer);
+        let error = read_cache_file(&cache_file).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(cached_files(&CollectionCache::open(cache_file)).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+//! Tells what a file is, by its extension (fast, without opening it) or by
+//! sniffing its content, and which tag readers try which mime type.
+use super::{
+    super::common::config,
+    tag_readers::{TagReader, TAG_READERS},
+};
+use std::{collections::HashMap, path::Path};
+
+/// Extensions that tell enough to not look into the file, the most
+/// frequent ones in audiobook folders (covers, booklets, playlists).
+/// Playlists are always known by their extension, sniffing them gives text.
+static KNOWN_EXTENSIONS: [(&str, &str); 34] = [
+    ("mp3", "audio/mpeg"),
+    ("m4a", "audio/x-m4a"),
+    ("m4b", "audio/x-m4b"),
+    ("mp4", "audio/mp4"),
+    ("aac", "audio/aac"),
+    ("flac", "audio/flac"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/x-opus+ogg"),
+    ("wav", "audio/x-wav"),
+    ("wma", "audio/x-ms-wma"),
+    ("m3u", "audio/x-mpegurl"),
+    ("m3u8", "audio/x-mpegurl"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("gif", "image/gif"),
+    ("bmp", "image/bmp"),
+    ("webp", "image/webp"),
+    ("pdf", "application/pdf"),
+    ("epub", "application/epub+zip"),
+    ("zip", "application/zip"),
+    ("cue", "application/x-cue"),
+    ("txt", "text/plain"),
+    ("nfo", "text/plain"),
+    ("log", "text/plain"),
+    ("sfv", "text/plain"),
+    ("md5", "text/plain"),
+    ("md", "text/markdown"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("xml", "application/xml"),
+    ("json", "application/json"),
+    ("db", "application/octet-stream"),
+];
+
+/// What a file is, as far as the search is concerned.
+#[derive(Debug, PartialEq)]
+pub enum FileClass {
+    /// audio (some audio files have a video mime type) with its mime type suffix
+    Audio(String),
+    /// a playlist, telling the files of a book
+    Playlist,
+    /// an archive, with files of books inside
+    Archive,
+    /// media without anything to analyze
+    Other,
+    /// no media at all, like covers and documents
+    Ignored,
+    /// not even a proper mime type
+    Faulty,
+}
+
+/// Classifies by mime type, regardless of where it came from.
+pub fn classify(mime_type: &str) -> FileClass {
+    match mime_type.split_once('/') {
+        Some((_, suffix)) if config::data::PLAYLIST_FORMATS.contains(&suffix) => {
+            FileClass::Playlist
+        }
+        Some((_, suffix)) if config::data::ARCHIVE_FORMATS.contains(&suffix) => FileClass::Archive,
+        Some((prefix, suffix)) if !suffix.contains('/') => match prefix {
+            "audio" | "video" => FileClass::Audio(suffix.to_string()),
+            "text" | "application" | "image" => FileClass::Ignored,
+            _ => {
+                error!("unknown mime type {:?}", mime_type);
+                FileClass::Other
+            }
+        },
+        _ => FileClass::Faulty,
+    }
+}
+
+/// Which extension is which mime type and which mime type suffix is read by
+/// which tag readers. Both can be changed by the user.
+#[derive(Clone, Debug)]
+pub struct MimeMapping {
+    /// lower case extension to mime type, used to classify without sniffing
+    pub extensions: HashMap<String, String>,
+    /// mime type suffix to the names of the tag readers to try first
+    pub readers: HashMap<String, Vec<String>>,
+}
+
+impl Default for MimeMapping {
+    /// The known extensions and the suffixes each tag reader knows.
+    fn default() -> Self {
+        let extensions = KNOWN_EXTENSIONS
+            .iter()
+            .map(|(extension, mime_type)| (extension.to_string(), mime_type.to_string()))
+            .collect();
+        let mut readers = HashMap::<String, Vec<String>>::new();
+        for tag_reader in TAG_READERS.iter() {
+            for suffix in tag_reader.known_suffixes() {
+                readers
+                    .entry(suffix.to_string())
+                    .or_default()
+                    .push(tag_reader.name().to_string());
+            }
+        }
+        Self {
+            extensions,
+            readers,
+        }
+    }
+}
+
+impl MimeMapping {
+    /// True if the extension is the one of a playlist.
+    pub fn is_playlist(&self, path: &Path) -> bool {
+        self.mime_of_extension(path)
+            .is_some_and(|mime_type| classify(mime_type) == FileClass::Playlist)
+    }
+
+    /// The mime type by extension, none if the content has to tell.
+    pub fn mime_of_extension(&self, path: &Path) -> Option<&str> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        self.extensions.get(&extension).map(String::as_str)
+    }
+
+    /// True if some tag reader is meant to read the suffix.
+    pub fn is_mapped(&self, suffix: &str) -> bool {
+        self.readers
+            .get(suffix)
+            .is_some_and(|names| !names.is_empty())
+    }
+
+    /// The tag readers to try for the suffix: the mapped ones first and,
+    /// if those reject the file, all others in their usual order.
+    pub fn readers_for(&self, suffix: &str) -> Vec<&'static (dyn TagReader<'static> + Sync)> {
+        let mapped = self.readers.get(suffix).map(Vec::as_slice).unwrap_or(&[]);
+        let first = mapped
+            .iter()
+            .filter_map(|name| TAG_READERS.iter().find(|r| r.name() == name));
+        let others = TAG_READERS
+            .iter()
+            .filter(|r| !mapped.iter().any(|name| name == r.name()));
+        first.chain(others).map(|r| r.as_ref()).collect()
+    }
+
+    /// Takes `extension=mime/type`, like `m4r=audio/x-m4a`.
+    pub fn map_extension(&mut self, assignment: &str) -> Result<(), String> {
+        let (extension, mime_type) = split_assignment(assignment)?;
+        if classify(mime_type) == FileClass::Faulty {
+            return Err(format!("{} is no mime type", mime_type));
+        }
+        self.extensions.insert(
+            extension.trim_start_matches('.').to_lowercase(),
+            mime_type.to_string(),
+        );
+        Ok(())
+    }
+
+    /// Takes `suffix=reader,reader`, like `x-wav=id3`. Nothing after `=`
+    /// lets all readers try in their usual order.
+    pub fn map_readers(&mut self, assignment: &str) -> Result<(), String> {
+        let (suffix, names) = split_assignment(assignment)?;
+        let names: Vec<String> = names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect();
+        if let Some(unknown) = names
+            .iter()
+            .find(|&name| !TAG_READERS.iter().any(|r| r.name() == name))
+        {
+            let known: Vec<&str> = TAG_READERS.iter().map(|r| r.name()).collect();
+            return Err(format!(
+                "there is no tag reader {}, only {}",
+                unknown,
+                known.join(", ")
+            ));
+        }
+        self.readers.insert(suffix.to_string(), names);
+        Ok(())
+    }
+}
+
+fn split_assignment(assignment: &str) -> Result<(&str, &str), String> {
+    match assignment.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim(), value.trim())),
+        _ => Err(format!("{} is not of the form key=value", assignment)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(readers: Vec<&'static (dyn TagReader<'static> + Sync)>) -> Vec<&'static str> {
+        readers.iter().map(|r| r.name()).collect()
+    }
+
+    #[test]
+    fn extensions_and_readers() {
+        let mut mapping = MimeMapping::default();
+        let mime_type = mapping.mime_of_extension(Path::new("/books/Orwell/01.MP3"));
+        assert_eq!(mime_type, Some("audio/mpeg"));
+        assert_eq!(
+            mime_type.map(classify),
+            Some(FileClass::Audio("mpeg".into()))
+        );
+        assert_eq!(classify("image/jpeg"), FileClass::Ignored);
+        assert_eq!(classify("audio/x-mpegurl"), FileClass::Playlist);
+        assert_eq!(
+            classify("application/vnd.apple.mpegurl"),
+            FileClass::Playlist
+        );
+        assert_eq!(classify("application/zip"), FileClass::Archive);
+        assert_eq!(classify("application/epub+zip"), FileClass::Ignored);
+        assert_eq!(classify("nonsense"), FileClass::Faulty);
+        assert_eq!(mapping.mime_of_extension(Path::new("README")), None);
+
+        // mapped readers first, then all others
+        assert_eq!(
+            names(mapping.readers_for("mpeg")),
+            ["id3", "mp3", "mp4", "flac", "ogg"]
+        );
+        assert!(!mapping.is_mapped("x-wav"));
+        assert_eq!(names(mapping.readers_for("x-wav")).len(), TAG_READERS.len());
+
+        mapping.map_readers("x-wav=mp3, id3").unwrap();
+        assert!(mapping.is_mapped("x-wav"));
+        assert_eq!(
+            names(mapping.readers_for("x-wav")),
+            ["mp3", "id3", "mp4", "flac", "ogg"]
+        );
+        assert!(mapping.map_readers("x-wav=vinyl").is_err());
+
+        mapping.map_extension(".M4R=audio/x-m4a").unwrap();
+        assert_eq!(
+            mapping.mime_of_extension(Path::new("ring.m4r")),
+            Some("audio/x-m4a")
+        );
+        assert!(mapping.map_extension("m4r").is_err());
+    }
+}
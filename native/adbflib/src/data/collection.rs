@@ -1,25 +1,35 @@
 //! The collection keeps and maintains all audio data.
 use super::{
-    super::common::config,
+    archive::Archive,
+    audio_book::AudioBook,
     audio_info::{AudioInfo, AudioInfoKey, Container},
-    tag_readers::{
-        CommonAudioInfo, FlacTagReader, ID3TagReader, MP3TagReader, MP4TagReader, TagReader,
-    },
+    cache::{Analysis, CacheEntry, CacheLookup, CollectionCache},
+    classify::{self, FileClass},
+    cover::{self, Cover},
+    cue::CueSheets,
+    file_id::{FileId, Visited},
+    filter::{self, IgnoreFiles, PathFilter},
+    kind::BookKind,
+    metric::KeyMetric,
+    options::ScanOptions,
+    payload,
+    playlist::Playlist,
+    report::{DuplicateReport, Match},
+    sidecar::Sidecar,
+    tag_readers::{self, AudioSource, CommonAudioInfo},
 };
 use libp2p::core::PeerId;
+use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use std::{
-    collections::HashSet,
     fs::{self, DirEntry},
-    io::{self, BufReader},
+    io::{self, BufReader, Seek, SeekFrom},
     mem,
-    path::Path,
+    path::{Path, PathBuf},
     sync::{Arc as SArc, Mutex as SMutex},
     time::Duration,
 };
 use tree_magic_mini;
 
-static TOLERANCE: usize = 5;
-/// distance of Levenshtein-algorithm
 static ID3_CAPACITY: usize = 1024;
 /// capacity to read small portion of file
 
@@ -27,14 +37,35 @@ static ID3_CAPACITY: usize = 1024;
 pub struct Collection {
     /// This collection contains all data
     stats: Stats,
+    /// Analyzed data of the last run
+    cache: CollectionCache,
+    /// Options chosen for this run
+    options: ScanOptions,
+    /// Which books are duplicates of which
+    report: DuplicateReport,
+    /// Files and folders visited during this run
+    visited: Visited,
 }
 /// Only some statistics
+#[derive(Default)]
 pub struct FilesStat {
     pub analyzed: u32,
     pub faulty: u32,
     pub searched: u32,
     pub other: u32,
     pub duplicates: u32,
+    /// playlists telling which files make a book
+    pub playlists: u32,
+    /// playlist entries pointing to missing files
+    pub broken: u32,
+    /// ZIP archives looked into, their entries are counted as files
+    pub archives: u32,
+    /// files of music and podcasts, left out when only audiobooks are indexed
+    pub skipped: u32,
+    /// symlinks leading back to a folder above
+    pub cycles: u32,
+    /// files and folders reached a second time (links, mounts), not searched again
+    pub links: u32,
 }
 impl FilesStat {
     /// Adds stats from one to the other,
@@ -45,6 +76,12 @@ impl FilesStat {
         self.faulty += other.faulty;
         self.searched += other.searched;
         self.other += other.other;
+        self.playlists += other.playlists;
+        self.broken += other.broken;
+        self.archives += other.archives;
+        self.skipped += other.skipped;
+        self.cycles += other.cycles;
+        self.links += other.links;
     }
 }
 
@@ -53,12 +90,166 @@ struct Stats {
     files: FilesStat,
 }
 
-type FileFn =
-    dyn Fn(&mut Collection, SArc<SMutex<Container>>, &DirEntry, &mut FilesStat) -> io::Result<()>;
+/// Everything the searching threads need from the collection, to be
+/// shared without locking the collection.
+#[derive(Clone)]
+pub struct Visitor {
+    cache: CacheLookup,
+    options: ScanOptions,
+    visited: Visited,
+    filter: PathFilter,
+}
+
+/// What was found in a search path, collected by each thread on its own
+/// and merged afterwards.
+#[derive(Default)]
+pub struct Harvest {
+    stats: FilesStat,
+    /// with their canonical path, to find them in playlists
+    tracks: Vec<(AudioInfo, Option<PathBuf>)>,
+    /// by the file names they are cached for
+    cache_entries: Vec<(String, CacheEntry)>,
+    playlists: Vec<Playlist>,
+}
+
+impl Harvest {
+    /// Counts the result of a single file and keeps its track.
+    fn take(&mut self, outcome: FileOutcome) {
+        self.stats.searched += 1;
+        match outcome {
+            FileOutcome::Cached(file_name, cached) => {
+                self.count(&cached.analysis);
+                if let Some(audio_info) = cached.analysis.track() {
+                    self.keep(audio_info.clone());
+                }
+                self.cache_entries.push((file_name, cached));
+            }
+            FileOutcome::Analyzed {
+                file_name,
+                on_disk,
+                analysis,
+                companions,
+            } => {
+                self.count(&analysis);
+                // remember for the next run, also files without a track,
+                // entries by their archive
+                if let Some(entry) = fs::metadata(on_disk)
+                    .ok()
+                    .and_then(|metadata| CacheEntry::new(&metadata, &companions, analysis.clone()))
+                {
+                    self.cache_entries.push((file_name, entry));
+                }
+                if let Analysis::Track(audio_info) = analysis {
+                    self.keep(*audio_info);
+                }
+            }
+            FileOutcome::Faulty => self.stats.faulty += 1,
+            FileOutcome::Other => self.stats.other += 1,
+            FileOutcome::Archive(outcomes) => {
+                self.stats.archives += 1;
+                for outcome in outcomes {
+                    self.take(outcome);
+                }
+            }
+            FileOutcome::Playlist(playlist) => {
+                self.stats.playlists += 1;
+                self.stats.broken += playlist.broken.len() as u32;
+                self.playlists.push(playlist);
+            }
+            FileOutcome::Ignored => (),
+        }
+    }
+
+    /// Files rejected by all tag readers are faulty, all others analyzed.
+    fn count(&mut self, analysis: &Analysis) {
+        match analysis {
+            Analysis::Rejected => self.stats.faulty += 1,
+            Analysis::Track(_) | Analysis::Incomplete => self.stats.analyzed += 1,
+        }
+    }
+
+    /// Resolves the path here in the searching thread, not later while
+    /// the container is locked.
+    fn keep(&mut self, audio_info: AudioInfo) {
+        let canonical = Path::new(&audio_info.file_name).canonicalize().ok();
+        self.tracks.push((audio_info, canonical));
+    }
+
+    fn merge(mut self, other: Harvest) -> Self {
+        self.stats.add(&other.stats);
+        self.tracks.extend(other.tracks);
+        self.cache_entries.extend(other.cache_entries);
+        self.playlists.extend(other.playlists);
+        self
+    }
+}
+
+/// What the files of a folder share: CUE sheets, metadata files and the cover.
+#[derive(Default)]
+struct FolderInfo {
+    cue_sheets: CueSheets,
+    sidecar: Sidecar,
+    cover: Option<Cover>,
+}
+
+impl FolderInfo {
+    /// Reads what is among the files of a folder.
+    fn of(paths: &[PathBuf]) -> Self {
+        Self {
+            cue_sheets: CueSheets::of(paths),
+            sidecar: Sidecar::of(paths),
+            cover: Cover::of_folder(paths),
+        }
+    }
+
+    /// The files completing the tags of the file, their changes outdate its cache entry.
+    fn companions(&self, path: &Path) -> Vec<PathBuf> {
+        let mut companions: Vec<PathBuf> = self
+            .cue_sheets
+            .get(path)
+            .map(|cue| cue.sheet.clone())
+            .into_iter()
+            .collect();
+        companions.extend(self.sidecar.files.iter().cloned());
+        companions.extend(
+            self.cover
+                .as_ref()
+                .map(|cover| PathBuf::from(&cover.source)),
+        );
+        companions
+    }
+}
+
+/// What was found out about a single file, by any of the parallel workers.
+enum FileOutcome {
+    /// unchanged since the last run
+    Cached(String, CacheEntry),
+    /// tags were read or rejected, with the file on disk (the archive of
+    /// an entry) and the files that completed the tags
+    Analyzed {
+        file_name: String,
+        on_disk: String,
+        analysis: Analysis,
+        companions: Vec<PathBuf>,
+    },
+    Faulty,
+    Other,
+    /// its entries make a book
+    Playlist(Playlist),
+    /// what was found out about each entry of an archive
+    Archive(Vec<FileOutcome>),
+    /// no audio file
+    Ignored,
+}
 
 impl Collection {
     /// Sets up the whole collection that books all threads.
     pub fn new() -> Collection {
+        Collection::with_options(ScanOptions::default())
+    }
+
+    /// Sets up the collection with the options chosen by the user.
+    pub fn with_options(options: ScanOptions) -> Collection {
         Collection {
             stats: Stats {
                 memory: 0,
@@ -68,176 +259,100 @@ impl Collection {
                     searched: 0,
                     other: 0,
                     duplicates: 0,
+                    playlists: 0,
+                    broken: 0,
+                    archives: 0,
+                    skipped: 0,
+                    cycles: 0,
+                    links: 0,
                 },
             },
+            cache: CollectionCache::new(),
+            options,
+            report: DuplicateReport::new(),
+            visited: Visited::default(),
         }
     }
 
+    /// The metric keys of books are compared with.
+    pub fn key_metric(&self) -> KeyMetric {
+        self.options.similarity.metric
+    }
+
+    /// Loads the analyzed data of the last run, unchanged files
+    /// will then not be opened again.
+    pub fn load_cache(&mut self) {
+        self.cache = CollectionCache::load();
+    }
+
+    /// Stores the analyzed data for the next run.
+    pub fn store_cache(&mut self, searched_paths: &[String]) {
+        self.cache
+            .store(searched_paths)
+            .unwrap_or_else(|e| warn!("could not store the collection cache: {}", e));
+    }
+
     /// Return memory usage
     pub fn memory(&self) -> u64 {
         self.stats.memory
     }
 
-    /// The function that runs from a given path
-    pub fn visit_path(
-        &mut self,
-        container_handle: SArc<SMutex<Container>>,
-        dir: &Path,
-        cb: &FileFn,
-    ) -> io::Result<FilesStat> {
-        let mut file_stats = FilesStat {
-            analyzed: 0,
-            faulty: 0,
-            searched: 0,
-            other: 0,
-            duplicates: 0,
-        };
-
-        if dir.is_dir() {
-            // todo: go with free threads in the search with rayon
-            for entry in fs::read_dir(dir)? {
-                let mut loop_file_stats = &mut file_stats;
-
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    let file_stats_loop = self.visit_path(container_handle.clone(), &path, cb)?;
-                    loop_file_stats.add(&file_stats_loop);
-                } else {
-                    cb(self, container_handle.clone(), &entry, &mut loop_file_stats).or_else(
-                        |io_error| {
-                            warn!("{:?}", io_error);
-                            Err(io_error)
-                        },
-                    )?
-                }
-            }
-        }
-        Ok(file_stats)
+    /// Return the duplicates found so far
+    pub fn duplicate_report(&self) -> DuplicateReport {
+        self.report.clone()
     }
 
-    /// the function to check all files separately
-    pub fn visit_files(
-        col: &mut Collection,
-        data: SArc<SMutex<Container>>,
-        cb: &DirEntry,
-        file_stats: &mut FilesStat,
-    ) -> io::Result<()> {
-        // count stats
-        col.stats.files.searched += 1;
-        file_stats.searched += 1;
-
-        if let Some(mime_type) = tree_magic_mini::from_filepath(&cb.path()) {
-            let vec_type: Vec<&str> = mime_type.split("/").collect();
-            if vec_type.len() == 2 {
-                let (prefix, suffix) = (vec_type[0], vec_type[1]);
-                match prefix {
-                    // some audio files have video-mimetype
-                    "audio" | "video" => {
-                        if config::data::IGNORE_AUDIO_FORMATS
-                            .iter()
-                            .any(|&s| s == suffix)
-                        {
-                            col.stats.files.other += 1;
-                            file_stats.other += 1;
-                            Ok(())
-                        } else {
-                            col.visit_audio_files(data, suffix, &cb.path(), file_stats)
-                                .or_else(|_| {
-                                    col.stats.files.faulty += 1;
-                                    file_stats.faulty += 1;
-                                    error!("ts: {:?}", mime_type);
-                                    Err(io::Error::new(io::ErrorKind::Other, "unknown audio file!"))
-                                })
-                        }
-                    }
-                    "text" | "application" | "image" => Ok(()),
-                    _ => {
-                        error!("[{:?}]{:?}", prefix, cb.path());
-                        col.stats.files.other += 1;
-                        file_stats.other += 1;
-                        Ok(())
-                    }
-                }
-            } else {
-                col.stats.files.faulty += 1;
-                file_stats.faulty += 1;
-                Ok(())
-            }
-        } else {
-            // not readable mime-type is no error
-            Ok(())
+    /// What the searching threads of a search path need, see `Visitor::visit_path`.
+    pub fn visitor(&self, root: &Path) -> Visitor {
+        let mut rules = vec![&self.options.rules];
+        rules.extend(
+            self.options
+                .path_rules
+                .iter()
+                .filter(|(path, _)| {
+                    let path = Path::new(path);
+                    path == root || path.canonicalize().is_ok_and(|path| path == root)
+                })
+                .map(|(_, path_rules)| path_rules),
+        );
+        Visitor {
+            cache: self.cache.lookup_handle(),
+            options: self.options.clone(),
+            visited: self.visited.clone(),
+            filter: PathFilter::new(root, &rules),
         }
     }
 
-    /// Check the file and retrieve the meta-data info
-    fn visit_audio_files<'a>(
+    /// Takes over what was found in a search path: its statistics, its cache
+    /// entries and its tracks, which then are complete books to be shelved.
+    pub fn take_harvest(
         &mut self,
-        data: SArc<SMutex<Container>>,
-        suffix: &'a str,
-        cb: &Path,
-        file_stats: &mut FilesStat,
-    ) -> Result<(), ()> {
-        // open file only once
-        // fixme: fix unwraps here
-        let file_name = cb.to_str().unwrap();
-        let file = std::fs::File::open(file_name).unwrap();
-        let mut file_buffer = BufReader::with_capacity(ID3_CAPACITY, file);
-
-        let mut processed = false;
-        let mut all_known_suffixes = HashSet::<&str>::new();
-
-        // cozy little helper (capturing suffix)
-        let suffix_has = |v: Vec<&str>| v.iter().any(|&s| s == suffix);
-
-        // 2nd cozy helper
-        let mut analyze = |tag_reader: &Box<dyn TagReader<'static> + Sync>| {
-            if !processed {
-                if suffix_has(tag_reader.known_suffixes()) {
-                    if let Ok(tag_data) = tag_reader.read_tag_from(&mut file_buffer) {
-                        self.analyze_tag(
-                            data.clone(),
-                            file_stats,
-                            file_name.to_string(),
-                            &tag_data,
-                        );
-                        processed = true;
-                    }
-                }
-            }
-        };
-
-        // only to be done once
-        lazy_static! {
-            static ref ANALYZE_ORDER: [Box<dyn TagReader<'static> + Sync>; 4] = [
-                Box::new(MP4TagReader),
-                Box::new(FlacTagReader),
-                Box::new(ID3TagReader),
-                Box::new(MP3TagReader),
-            ];
-        }
-        // analyze according to order
-        for reader in ANALYZE_ORDER.iter().to_owned() {
-            analyze(reader);
-            reader
-                .known_suffixes()
-                .iter_mut()
-                .all(|mime_suffix| all_known_suffixes.insert(*mime_suffix));
+        container_handle: SArc<SMutex<Container>>,
+        root: &Path,
+        harvest: Harvest,
+    ) -> FilesStat {
+        let Harvest {
+            mut stats,
+            tracks,
+            cache_entries,
+            playlists,
+        } = harvest;
+        self.stats.files.add(&stats);
+        for (file_name, entry) in cache_entries {
+            self.cache.remember(file_name, entry);
         }
-
-        if !processed {
-            if all_known_suffixes.contains(suffix) {
-                warn!(
-                    "though known, could not process mime-type suffix: {} - path: {}!",
-                    suffix, file_name
-                );
-            } else {
-                warn!("this suffix {} has no tag reader yet!", suffix);
+        {
+            let mut locked_container = container_handle.lock().unwrap();
+            // playlists first, they decide the books of their tracks
+            for playlist in &playlists {
+                locked_container.add_playlist(playlist);
+            }
+            for (track, canonical) in tracks {
+                locked_container.add_track(track, canonical.as_deref());
             }
-            self.stats.files.faulty += 1;
-            file_stats.faulty += 1;
         }
-        Ok(())
+        self.shelve_books(container_handle, root, &mut stats);
+        stats
     }
 
     pub fn print_stats(&self, peer_id: &PeerId, threads: usize) {
@@ -250,7 +365,11 @@ impl Collection {
              searched files       : {files_searched:>width$}\n\
              duplicate files      : {files_duplicate:>width$}\n\
              irrelevant files     : {files_irrelevant:>width$}\n\
-             faulty files         : {files_faulty:>width$}\n",
+             faulty files         : {files_faulty:>width$}\n\
+             playlists            : {playlists:>width$}\n\
+             broken entries       : {broken:>width$}\n\
+             archives             : {archives:>width$}\n\
+             skipped files        : {skipped:>width$}\n",
             id = peer_id.to_string().to_uppercase(),
             nr_threads = threads,
             mem = self.stats.memory / 1000,
@@ -259,89 +378,578 @@ impl Collection {
             files_duplicate = self.stats.files.duplicates,
             files_irrelevant = self.stats.files.other,
             files_faulty = self.stats.files.faulty, // awesome, really
+            playlists = self.stats.files.playlists,
+            broken = self.stats.files.broken,
+            archives = self.stats.files.archives,
+            skipped = self.stats.files.skipped,
             width = 5
         );
 
         info!("{}", output_string);
     }
 
-    fn analyze_tag<'a>(
+    /// Puts the books of an already searched path into the container,
+    /// if there is no exact match yet.
+    fn shelve_books(
         &mut self,
         data: SArc<SMutex<Container>>,
+        root: &Path,
         file_stats: &mut FilesStat,
-        file_name: String,
-        audio_info: &'a CommonAudioInfo,
     ) {
-        self.stats.files.analyzed += 1;
-        file_stats.analyzed += 1;
-
-        // audio book genre set is a strong indicator
-        // many discs and total discs is a strong indicator
-        let mut has_enough_information = true;
-
-        // artist + song name is key for bktree
-        if audio_info.artist.is_empty() && audio_info.title.is_empty() {
-            has_enough_information = false;
-        }
-
-        if has_enough_information {
-            let key = AudioInfoKey::new(&audio_info.artist, &audio_info.title);
-
-            // a) filter numbers / remove
-            // b) use album name / substract it?
+        let ref mut locked_container = data.lock().unwrap();
+        for audio_book in locked_container.take_books_below(root) {
+            let kind = audio_book.kind();
+            if self.options.audiobooks_only && kind != BookKind::AudioBook {
+                trace!("{:?} in '{}' is not indexed", kind, audio_book.folder);
+                let nr_files = audio_book.file_count() as u32;
+                self.stats.files.skipped += nr_files;
+                file_stats.skipped += nr_files;
+                continue;
+            }
+            let key = audio_book.key(&self.options.key_normalization);
+            // the webui shows the covers of the books
+            if let Some(cover) = audio_book.cover() {
+                cover::register(cover);
+            }
 
-            let ref mut locked_container = data.lock().unwrap();
-            let (vec_exact_match, vec_similarities) = locked_container.find(&key, TOLERANCE);
+            let (mut vec_exact_match, vec_similarities) =
+                locked_container.find(&key, self.options.similarity.tolerance_of(&key));
             if !vec_similarities.is_empty() {
                 trace!("close: {:?} to {:?},", &vec_similarities, &key);
             }
+            // other volumes of a series are alike, but no duplicates
+            vec_exact_match
+                .retain(|known_audio_book| !known_audio_book.is_other_volume(&audio_book));
+            // and other narrators make other editions
+            let (editions, vec_exact_match): (Vec<_>, Vec<_>) = vec_exact_match
+                .into_iter()
+                .partition(|known_audio_book| known_audio_book.is_other_edition(&audio_book));
+            for similar_key in vec_similarities {
+                let (similar_books, _) = locked_container.find(similar_key, 0);
+                let (similar_editions, similar_books): (Vec<&AudioBook>, Vec<&AudioBook>) =
+                    similar_books
+                        .iter()
+                        .map(|b| b.as_ref())
+                        .filter(|similar_book| !similar_book.is_other_volume(&audio_book))
+                        .partition(|similar_book| similar_book.is_other_edition(&audio_book));
+                self.report_cluster(Match::Edition, similar_key, similar_editions, &audio_book);
+                self.report_cluster(Match::Similar, similar_key, similar_books, &audio_book);
+            }
+            let editions = editions.iter().map(|b| b.as_ref()).collect();
+            self.report_cluster(Match::Edition, &key, editions, &audio_book);
+            let exact_books = vec_exact_match.iter().map(|b| b.as_ref()).collect();
+            self.report_cluster(Match::Exact, &key, exact_books, &audio_book);
+            // byte identical audio is a duplicate, whatever the tags say
+            let nr_identical_files = audio_book
+                .tracks
+                .iter()
+                .filter(|track| match &track.payload_hash {
+                    Some(payload_hash) => match locked_container.known_payload(payload_hash) {
+                        Some(known_file) => {
+                            trace!(
+                                "identical audio: '{}' and '{}'",
+                                known_file,
+                                track.file_name
+                            );
+                            true
+                        }
+                        None => false,
+                    },
+                    None => false,
+                })
+                .count() as u32;
             // if exact match, don't insert!!
             if vec_exact_match.is_empty() {
+                self.stats.files.duplicates += nr_identical_files;
+                file_stats.duplicates += nr_identical_files;
+                locked_container.remember_payloads(&audio_book);
                 // todo: also decide when to not add and insert then
-
-                let key = key.clone();
-                let audio_info = AudioInfo {
-                    duration: audio_info.duration,
-                    album: audio_info
-                        .album
-                        .as_ref()
-                        .unwrap_or(&"no album".to_string())
-                        .to_string(),
-                    file_name: file_name.to_string(),
-                };
-                let value = Box::new(audio_info.clone());
+                let value = Box::new(audio_book);
                 let mem_size = mem::size_of_val(&key) + mem::size_of_val(&value);
-                locked_container.insert(key.clone(), value);
+                locked_container.insert(key, value);
                 self.stats.memory += mem_size as u64;
             } else {
-                // exact match with certain AudioInfo
-                self.stats.files.duplicates += vec_exact_match.len() as u32;
-                for new_audio_info in vec_exact_match {
-                    let time_distance = new_audio_info.duration.checked_sub(audio_info.duration);
+                // exact match with certain AudioBook, all its files are duplicates
+                let nr_files = audio_book.file_count() as u32;
+                self.stats.files.duplicates += nr_files;
+                file_stats.duplicates += nr_files;
+                // chapters and covers are compared in the report
+                for known_audio_book in vec_exact_match {
+                    let time_distance = known_audio_book.duration.checked_sub(audio_book.duration);
                     if let Some(diff) = time_distance {
                         if diff > Duration::from_secs(0) {
                             trace!(
-                                "same: but time differs {:?} seconds with album name old '{}' and new: '{}'!",
+                                "same: but time differs {:?} seconds with folder old '{}' and new: '{}'!",
                                 time_distance,
-                                new_audio_info.album,
-                                audio_info
-                                    .album
-                                    .as_ref()
-                                    .unwrap_or(&"no album".to_string())
-                                    .to_string(),
+                                known_audio_book.folder,
+                                audio_book.folder,
                             );
                         }
                     } else {
-                        warn!("an interesting duration error was found and item with album name '{}' could not be included!",
-                              audio_info
-                                  .album
-                                  .as_ref()
-                                  .unwrap_or(&"no album".to_string())
-                                  .to_string(),
+                        trace!(
+                            "same: but new book in '{}' is longer than the one in '{}'!",
+                            audio_book.folder,
+                            known_audio_book.folder,
                         )
                     }
                 }
+                let all_hashed = audio_book
+                    .tracks
+                    .iter()
+                    .all(|track| track.payload_hash.is_some());
+                if all_hashed && nr_identical_files == 0 {
+                    trace!(
+                        "same: but audio of book in '{}' is another recording!",
+                        audio_book.folder
+                    );
+                }
+                locked_container.remember_payloads(&audio_book);
+            }
+        }
+    }
+
+    /// Reports the new book together with the known ones, if there are any.
+    fn report_cluster<'a>(
+        &mut self,
+        matching: Match,
+        key: &AudioInfoKey,
+        mut cluster: Vec<&'a AudioBook>,
+        audio_book: &'a AudioBook,
+    ) {
+        if !cluster.is_empty() {
+            cluster.push(audio_book);
+            self.report.add(matching, key, &cluster);
+        }
+    }
+}
+
+impl Visitor {
+    /// The function that runs from a given path. Its folders and files are
+    /// visited in parallel, no lock is needed for it.
+    pub fn visit_path(&self, dir: &Path) -> io::Result<Harvest> {
+        self.visit_dir(dir, &[], &IgnoreFiles::default())
+    }
+
+    /// Visits the entries of a folder in parallel (rayon steals the work
+    /// of subfolders), and subfolders recursively. Each thread collects
+    /// into its own harvest. Folders already visited (by this or another
+    /// path) are skipped, the ancestors tell if it was a loop. So are
+    /// excluded or ignored ones, and folders without media.
+    fn visit_dir(
+        &self,
+        dir: &Path,
+        ancestors: &[FileId],
+        ignore_files: &IgnoreFiles,
+    ) -> io::Result<Harvest> {
+        let mut harvest = Harvest::default();
+        if !dir.is_dir() {
+            return Ok(harvest);
+        }
+        if filter::has_no_media(dir) {
+            trace!("{:?} has no media", dir);
+            return Ok(harvest);
+        }
+        let ignore_files = ignore_files.enter(dir);
+        let mut ancestors = ancestors.to_vec();
+        if let Some(id) = FileId::of(dir, &fs::metadata(dir)?) {
+            if ancestors.contains(&id) {
+                warn!("symlink loop: {:?} leads back to a folder above!", dir);
+                harvest.stats.cycles += 1;
+                return Ok(harvest);
+            }
+            if !self.visited.first_visit(id.clone()) {
+                trace!("{:?} was already visited", dir);
+                harvest.stats.links += 1;
+                return Ok(harvest);
+            }
+            ancestors.push(id);
+        }
+        let entries = fs::read_dir(dir)?.collect::<io::Result<Vec<DirEntry>>>()?;
+        let paths: Vec<PathBuf> = entries.iter().map(DirEntry::path).collect();
+        // single-file rips come with CUE sheets next to them, many
+        // books with metadata files of book managers
+        let folder = FolderInfo::of(&paths);
+        entries
+            .par_iter()
+            .zip(paths.par_iter())
+            .try_fold(Harvest::default, |mut harvest, (entry, path)| {
+                let path = path.clone();
+                let is_dir = path.is_dir();
+                if entry.file_type()?.is_symlink() && self.options.skip_symlinks {
+                    trace!("symlink {:?} skipped", path);
+                } else if !self.filter.allows(&path, is_dir) || ignore_files.ignores(&path, is_dir)
+                {
+                    trace!("{:?} excluded", path);
+                } else if is_dir {
+                    harvest = harvest.merge(self.visit_dir(&path, &ancestors, &ignore_files)?);
+                } else if self.is_visited(&path) {
+                    trace!("{:?} is a link to a file already visited", path);
+                    harvest.stats.links += 1;
+                } else {
+                    harvest.take(self.visit_file(&path, &folder));
+                }
+                Ok(harvest)
+            })
+            .try_reduce(Harvest::default, |harvest, other| Ok(harvest.merge(other)))
+            .map_err(|io_error: io::Error| {
+                warn!("{:?}", io_error);
+                io_error
+            })
+    }
+
+    /// A file that was already reached by another (hard or symbolic) link.
+    fn is_visited(&self, path: &Path) -> bool {
+        fs::metadata(path)
+            .ok()
+            .and_then(|metadata| FileId::of(path, &metadata))
+            .is_some_and(|id| !self.visited.first_visit(id))
+    }
+
+    /// the function to check all files separately
+    fn visit_file(&self, path: &Path, folder: &FolderInfo) -> FileOutcome {
+        // unchanged files are taken from the cache without opening them
+        if let (Some(file_name), Ok(metadata)) = (path.to_str(), fs::metadata(path)) {
+            let companions = folder.companions(path);
+            let cached = self
+                .cache
+                .lookup(file_name, &metadata, &companions)
+                .filter(|cached| !self.options.hash_audio || has_payload_hash(cached));
+            if let Some(cached) = cached {
+                return FileOutcome::Cached(file_name.to_string(), cached);
+            }
+        }
+
+        // in fast mode the extension tells, only unknown ones are sniffed
+        let mime_mapping = &self.options.mime_mapping;
+        let known_mime_type = if self.options.fast_classify || mime_mapping.is_playlist(path) {
+            mime_mapping.mime_of_extension(path)
+        } else {
+            None
+        };
+        match known_mime_type
+            .or_else(|| tree_magic_mini::from_filepath(path))
+            .map(classify::classify)
+        {
+            Some(FileClass::Audio(suffix)) => self
+                .visit_audio_files(&suffix, path, folder)
+                .unwrap_or_else(|_| {
+                    error!("ts: {:?} {:?}", suffix, path);
+                    FileOutcome::Faulty
+                }),
+            Some(FileClass::Playlist) => match Playlist::read(path) {
+                Ok(playlist) => {
+                    for entry in &playlist.broken {
+                        warn!("playlist {:?} has a broken entry: {}", path, entry);
+                    }
+                    FileOutcome::Playlist(playlist)
+                }
+                Err(e) => {
+                    warn!("could not read playlist {:?}: {}", path, e);
+                    FileOutcome::Faulty
+                }
+            },
+            Some(FileClass::Archive) if self.options.scan_archives => self.visit_archive(path),
+            Some(FileClass::Other) => FileOutcome::Other,
+            Some(FileClass::Faulty) => FileOutcome::Faulty,
+            // not readable mime-type is no error, archives are opt-in
+            Some(FileClass::Archive) | Some(FileClass::Ignored) | None => FileOutcome::Ignored,
+        }
+    }
+
+    /// Check the file and retrieve the meta-data info
+    fn visit_audio_files(
+        &self,
+        suffix: &str,
+        cb: &Path,
+        folder: &FolderInfo,
+    ) -> Result<FileOutcome, ()> {
+        // open file only once
+        let file_name = cb.to_str().ok_or(())?;
+        let file = std::fs::File::open(file_name).map_err(|_| ())?;
+        let mut file_buffer = AudioSource::File(BufReader::with_capacity(ID3_CAPACITY, file));
+        Ok(self.read_audio(suffix, file_name, None, &mut file_buffer, folder))
+    }
+
+    /// The audio entries of a ZIP archive, each read like a file.
+    fn visit_archive(&self, path: &Path) -> FileOutcome {
+        let (mut archive, metadata) = match Archive::open(path).and_then(|archive| {
+            let metadata = fs::metadata(path)?;
+            Ok((archive, metadata))
+        }) {
+            Ok(opened) => opened,
+            Err(e) => {
+                warn!("could not open archive {:?}: {}", path, e);
+                return FileOutcome::Faulty;
+            }
+        };
+        let mut outcomes = vec![];
+        for entry in archive.entries() {
+            let file_name = archive.file_name(&entry);
+            // entries are unchanged as long as their archive is
+            let cached = self
+                .cache
+                .lookup(&file_name, &metadata, &[])
+                .filter(|cached| !self.options.hash_audio || has_payload_hash(cached));
+            if let Some(cached) = cached {
+                outcomes.push(FileOutcome::Cached(file_name, cached));
+                continue;
+            }
+            // only the extension tells, entries cannot be sniffed without reading them
+            let class = self
+                .options
+                .mime_mapping
+                .mime_of_extension(Path::new(&entry))
+                .map(classify::classify);
+            outcomes.push(match class {
+                Some(FileClass::Audio(suffix)) => match archive.read(&entry) {
+                    Ok(mut source) => {
+                        let folder = FolderInfo::default();
+                        let archive = Some((archive.path(), entry.as_str()));
+                        self.read_audio(&suffix, &file_name, archive, &mut source, &folder)
+                    }
+                    Err(e) => {
+                        warn!("could not read {}: {}", file_name, e);
+                        FileOutcome::Faulty
+                    }
+                },
+                // playlists inside archives point to nothing on disk,
+                // archives inside archives are not opened
+                Some(FileClass::Playlist) | Some(FileClass::Archive) | Some(FileClass::Other) => {
+                    FileOutcome::Other
+                }
+                Some(FileClass::Faulty) => FileOutcome::Faulty,
+                Some(FileClass::Ignored) | None => FileOutcome::Ignored,
+            });
+        }
+        FileOutcome::Archive(outcomes)
+    }
+
+    /// Reads the tags of a file or of an archive entry (then given by archive
+    /// path and entry), completed by what its folder knows.
+    fn read_audio(
+        &self,
+        suffix: &str,
+        file_name: &str,
+        archive: Option<(&str, &str)>,
+        file_buffer: &mut AudioSource,
+        folder: &FolderInfo,
+    ) -> FileOutcome {
+        let cue = folder.cue_sheets.get(Path::new(file_name));
+        let companions = folder.companions(Path::new(file_name));
+        let analyzed = |analysis| FileOutcome::Analyzed {
+            file_name: file_name.to_string(),
+            on_disk: archive
+                .map_or(file_name, |(archive, _)| archive)
+                .to_string(),
+            analysis,
+            companions,
+        };
+        // the mapped readers first, if they reject the file all others
+        let mut read = None;
+        for tag_reader in self.options.mime_mapping.readers_for(suffix) {
+            match tag_reader.read_tag_from(file_buffer) {
+                Ok(tag_data) => {
+                    read = Some(tag_data);
+                    break;
+                }
+                Err(e) => trace!("{} reader rejected {}: {}", tag_reader.name(), file_name, e),
             }
         }
+        // without tags the stream is enough, if a CUE sheet or a metadata
+        // file tells about it
+        let described = cue.is_some() || !folder.sidecar.files.is_empty();
+        if read.is_none() && described {
+            read = tag_readers::read_untagged(file_buffer);
+        }
+        let mut tag_data = match read {
+            Some(tag_data) => tag_data,
+            None => {
+                if self.options.mime_mapping.is_mapped(suffix) {
+                    warn!(
+                        "though known, could not process mime-type suffix: {} - path: {}!",
+                        suffix, file_name
+                    );
+                } else {
+                    warn!("this suffix {} has no tag reader yet!", suffix);
+                }
+                return analyzed(Analysis::Rejected);
+            }
+        };
+
+        if let Some(cue) = cue {
+            cue.complete(&mut tag_data);
+        }
+        folder.sidecar.complete(&mut tag_data);
+        let cover = tag_data
+            .cover
+            .as_ref()
+            .and_then(|image| Cover::embedded(file_name, archive, image))
+            .or_else(|| folder.cover.clone());
+        let payload_hash = if self.options.hash_audio {
+            payload::hash_audio_payload(file_buffer).unwrap_or_else(|e| {
+                warn!("could not hash audio of {}: {}", file_name, e);
+                None
+            })
+        } else {
+            None
+        };
+        // the streams tell no size, some not even their bitrate
+        if let Some(quality) = tag_data.quality.as_mut() {
+            match file_buffer.seek(SeekFrom::End(0)) {
+                Ok(size) => quality.complete(size, tag_data.duration),
+                Err(e) => warn!("could not tell size of {}: {}", file_name, e),
+            }
+        }
+        analyzed(
+            match analyze_tag(
+                file_name.to_string(),
+                archive.map(|(archive, _)| archive.to_string()),
+                &tag_data,
+                payload_hash,
+                cue.map(|cue| cue.sheet.to_string_lossy().into_owned()),
+                cover,
+            ) {
+                Some(audio_info) => Analysis::Track(Box::new(audio_info)),
+                None => Analysis::Incomplete,
+            },
+        )
+    }
+}
+
+/// Entries of runs without hashing have to be hashed now, files without
+/// a track have nothing to hash.
+fn has_payload_hash(cached: &CacheEntry) -> bool {
+    cached
+        .analysis
+        .track()
+        .is_none_or(|audio_info| audio_info.payload_hash.is_some())
+}
+
+fn analyze_tag(
+    file_name: String,
+    archive: Option<String>,
+    audio_info: &CommonAudioInfo,
+    payload_hash: Option<String>,
+    cue_sheet: Option<String>,
+    cover: Option<Cover>,
+) -> Option<AudioInfo> {
+    // whether it is an audiobook is told for the whole book (see kind)
+    let mut has_enough_information = true;
+
+    // artist + song name is key for bktree, sidecars may have filled them in
+    if audio_info.artist.is_empty() && audio_info.title.is_empty() {
+        has_enough_information = false;
+    }
+
+    if has_enough_information {
+        Some(AudioInfo {
+            duration: audio_info.duration,
+            album: audio_info.album.clone(),
+            file_name,
+            archive,
+            artist: audio_info.artist.clone(),
+            title: audio_info.title.clone(),
+            album_artist: audio_info.album_artist.clone(),
+            track: audio_info.track,
+            disc: audio_info.disc,
+            total_discs: audio_info.total_discs,
+            chapters: audio_info.chapters.clone(),
+            payload_hash,
+            cue_sheet,
+            series: audio_info.series.clone(),
+            volume: audio_info.volume.clone(),
+            grouping: audio_info.grouping.clone(),
+            narrator: audio_info.narrator.clone(),
+            composer: audio_info.composer.clone(),
+            isbn: audio_info.isbn.clone(),
+            description: audio_info.description.clone(),
+            genre: audio_info.genre.clone(),
+            cover,
+            quality: audio_info.quality,
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(file_name: &str, narrator: &str) -> AudioInfo {
+        AudioInfo {
+            duration: Duration::from_secs(60 * 60),
+            album: Some("1984".to_string()),
+            file_name: file_name.to_string(),
+            artist: "George Orwell".to_string(),
+            title: "1984".to_string(),
+            track: Some(1),
+            narrator: Some(narrator.to_string()),
+            ..AudioInfo::default()
+        }
+    }
+
+    #[test]
+    fn other_narrator_is_an_edition() {
+        let mut collection = Collection::new();
+        let container = SArc::new(SMutex::new(Container::new()));
+        for (root, narrator) in [("/a", "Simon Prebble"), ("/b", "Frank Muller")] {
+            let harvest = Harvest {
+                tracks: vec![(track(&format!("{}/1984/01.mp3", root), narrator), None)],
+                ..Harvest::default()
+            };
+            collection.take_harvest(container.clone(), Path::new(root), harvest);
+        }
+        let report = collection.duplicate_report();
+        assert_eq!(report.clusters.len(), 1);
+        assert_eq!(report.clusters[0].matching, Match::Edition);
+        assert_eq!(report.clusters[0].books.len(), 2);
+        // an edition is no duplicate, both books are kept
+        assert_eq!(container.lock().unwrap().flush().len(), 2);
+    }
+
+    #[test]
+    fn other_disc_is_no_duplicate() {
+        let mut collection = Collection::new();
+        let container = SArc::new(SMutex::new(Container::new()));
+        let disc = |file_name: &str, disc| AudioInfo {
+            disc: Some(disc),
+            total_discs: Some(2),
+            ..track(file_name, "Simon Prebble")
+        };
+        let harvest = Harvest {
+            tracks: vec![
+                (disc("/a/1984/01.mp3", 1), None),
+                (disc("/a/1984/02.mp3", 2), None),
+            ],
+            ..Harvest::default()
+        };
+        collection.take_harvest(container.clone(), Path::new("/a"), harvest);
+        assert!(collection.duplicate_report().clusters.is_empty());
+        assert_eq!(container.lock().unwrap().flush().len(), 2);
+    }
+
+    #[test]
+    fn discs_of_one_folder_are_all_reported() {
+        let mut collection = Collection::new();
+        let container = SArc::new(SMutex::new(Container::new()));
+        let disc = |file_name: &str, disc| AudioInfo {
+            disc: Some(disc),
+            total_discs: Some(2),
+            ..track(file_name, "Simon Prebble")
+        };
+        for root in ["/a", "/b"] {
+            let harvest = Harvest {
+                tracks: vec![
+                    (disc(&format!("{}/1984/01.mp3", root), 1), None),
+                    (disc(&format!("{}/1984/02.mp3", root), 2), None),
+                ],
+                ..Harvest::default()
+            };
+            collection.take_harvest(container.clone(), Path::new(root), harvest);
+        }
+        let report = collection.duplicate_report();
+        assert_eq!(report.clusters.len(), 1);
+        assert_eq!(report.clusters[0].books.len(), 4);
     }
 }
@@ -0,0 +1,247 @@
+//! Covers of books, embedded in their tags or as image file in their folder.
+//! A small perceptual hash (dHash) tells whether two books show the same
+//! cover, even if it was scaled or compressed again. The image itself is
+//! not kept, it is read again when it is shown.
+use super::{
+    archive::Archive,
+    tag_readers::{self, AudioSource},
+};
+use image::imageops::FilterType;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+/// Image files taken as cover of the folder, the first found wins.
+static FOLDER_COVERS: [&str; 4] = ["cover", "folder", "front", "albumart"];
+static FOLDER_COVER_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+/// Hashes of the same cover differ in at most that many bits.
+static SAME_COVER_DISTANCE: u32 = 10;
+
+lazy_static! {
+    /// The covers of the collected books, to find their images by hash
+    static ref KNOWN_COVERS: RwLock<HashMap<u64, Cover>> = RwLock::new(HashMap::new());
+}
+
+/// The cover of a track (and so of its book).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Cover {
+    /// perceptual hash of the image
+    pub hash: u64,
+    /// the audio file with the cover in its tags, the archive with that
+    /// file, or the image file
+    pub source: String,
+    /// the audio file inside the archive
+    pub entry: Option<String>,
+    pub embedded: bool,
+}
+
+impl Cover {
+    /// The cover embedded in the tags of an audio file, or of an entry of
+    /// an archive (given by archive path and entry).
+    pub fn embedded(file_name: &str, archive: Option<(&str, &str)>, image: &[u8]) -> Option<Self> {
+        let (source, entry) = match archive {
+            Some((archive, entry)) => (archive, Some(entry.to_string())),
+            None => (file_name, None),
+        };
+        Some(Self {
+            hash: hash(image)?,
+            source: source.to_string(),
+            entry,
+            embedded: true,
+        })
+    }
+
+    /// The cover image among the files of a folder, if any.
+    pub fn of_folder(paths: &[PathBuf]) -> Option<Self> {
+        let path = folder_cover(paths)?;
+        let image = fs::read(path)
+            .map_err(|e| warn!("could not read cover {:?}: {}", path, e))
+            .ok()?;
+        match (hash(&image), path.to_str()) {
+            (Some(hash), Some(source)) => Some(Self {
+                hash,
+                source: source.to_string(),
+                entry: None,
+                embedded: false,
+            }),
+            _ => {
+                warn!("cover {:?} is no readable image", path);
+                None
+            }
+        }
+    }
+
+    /// The hash as used in urls, u64 is too big for javascript numbers.
+    pub fn id(&self) -> String {
+        format!("{:016x}", self.hash)
+    }
+
+    /// Both covers look the same.
+    pub fn is_same(&self, other: &Cover) -> bool {
+        distance(self.hash, other.hash) <= SAME_COVER_DISTANCE
+    }
+
+    /// Reads the image again, from the tags or the image file.
+    pub fn image(&self) -> io::Result<Vec<u8>> {
+        if !self.embedded {
+            return fs::read(&self.source);
+        }
+        let mut source = match &self.entry {
+            Some(entry) => Archive::open(Path::new(&self.source))?.read(entry)?,
+            None => AudioSource::File(BufReader::new(File::open(&self.source)?)),
+        };
+        tag_readers::read_cover(&mut source)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cover not found in tags"))
+    }
+}
+
+/// The image file taken as cover of the folder.
+pub fn folder_cover(paths: &[PathBuf]) -> Option<&PathBuf> {
+    FOLDER_COVERS.iter().find_map(|name| {
+        paths.iter().find(|path| {
+            let is_named = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| stem.eq_ignore_ascii_case(name));
+            let is_image = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    FOLDER_COVER_EXTENSIONS
+                        .iter()
+                        .any(|known| extension.eq_ignore_ascii_case(known))
+                });
+            is_named && is_image
+        })
+    })
+}
+
+/// The difference hash: the image shrunk to 9x8 gray pixels, a bit for each
+/// pixel brighter than its right neighbour.
+pub fn hash(image: &[u8]) -> Option<u64> {
+    let image = image::load_from_memory(image)
+        .map_err(|e| trace!("no cover image: {}", e))
+        .ok()?;
+    let pixels = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if pixels.get_pixel(x, y)[0] > pixels.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Some(hash)
+}
+
+/// Number of differing bits.
+pub fn distance(hash: u64, other: u64) -> u32 {
+    (hash ^ other).count_ones()
+}
+
+/// The mime type of a cover, covers are jpeg or png.
+pub fn mime_type(image: &[u8]) -> &'static str {
+    if image.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if image.starts_with(&[0xFF, 0xD8]) {
+        "image/jpeg"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Both ids are of covers looking the same, none if an id is no id.
+pub fn is_same_id(id: &str, other: &str) -> Option<bool> {
+    let (hash, other) = (
+        u64::from_str_radix(id, 16).ok()?,
+        u64::from_str_radix(other, 16).ok()?,
+    );
+    Some(distance(hash, other) <= SAME_COVER_DISTANCE)
+}
+
+/// Remembers the cover of a collected book.
+pub fn register(cover: &Cover) {
+    KNOWN_COVERS
+        .write()
+        .unwrap()
+        .entry(cover.hash)
+        .or_insert_with(|| cover.clone());
+}
+
+/// The cover of a collected book by its id, nothing else can be read.
+pub fn known(id: &str) -> Option<Cover> {
+    let hash = u64::from_str_radix(id, 16).ok()?;
+    KNOWN_COVERS.read().unwrap().get(&hash).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageOutputFormat, RgbImage};
+    use std::io::Cursor;
+
+    fn encoded(image: RgbImage, format: ImageOutputFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut bytes, format)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn same_cover_rescaled() {
+        let cover = |size: u32| {
+            RgbImage::from_fn(size, size, |x, y| {
+                let value = ((x * 7 + y * 3) * 255 / (size * 10)) as u8;
+                if x > size / 2 {
+                    image::Rgb([value, value, 255 - value])
+                } else {
+                    image::Rgb([255 - value, value, value])
+                }
+            })
+        };
+        let original = encoded(cover(300), ImageOutputFormat::Png);
+        let thumbnail = encoded(cover(64), ImageOutputFormat::Jpeg(60));
+        assert_eq!(mime_type(&original), "image/png");
+        assert_eq!(mime_type(&thumbnail), "image/jpeg");
+
+        let original = Cover::embedded("/books/01.mp3", None, &original).unwrap();
+        let thumbnail = Cover::embedded("/books/cover.jpg", None, &thumbnail).unwrap();
+        assert!(original.is_same(&thumbnail));
+        let mirrored = encoded(
+            image::imageops::flip_horizontal(&cover(300)),
+            ImageOutputFormat::Png,
+        );
+        let mirrored = Cover::embedded("", None, &mirrored).unwrap();
+        assert!(!original.is_same(&mirrored));
+        assert_eq!(is_same_id(&original.id(), &thumbnail.id()), Some(true));
+        assert_eq!(is_same_id(&original.id(), &mirrored.id()), Some(false));
+        assert_eq!(is_same_id(&original.id(), "no id"), None);
+        assert_eq!(hash(b"no image"), None);
+
+        register(&original);
+        assert_eq!(known(&original.id()), Some(original));
+        assert_eq!(known("not hex"), None);
+    }
+
+    #[test]
+    fn find_folder_cover() {
+        let paths = vec![
+            PathBuf::from("/books/01.mp3"),
+            PathBuf::from("/books/Folder.JPG"),
+            PathBuf::from("/books/cover.txt"),
+            PathBuf::from("/books/cover.png"),
+        ];
+        assert_eq!(
+            folder_cover(&paths),
+            Some(&PathBuf::from("/books/cover.png"))
+        );
+        assert_eq!(folder_cover(&paths[..3]), Some(&paths[1]));
+        assert_eq!(folder_cover(&paths[..1]), None);
+    }
+}
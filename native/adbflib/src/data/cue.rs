@@ -0,0 +1,289 @@
+//! Reads CUE sheets, which come with single-file rips and tell the tracks
+//! (chapters) of the audio file they refer to, with performer and title.
+use super::{audio_info::Chapter, tag_readers::CommonAudioInfo};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// CUE times are minutes, seconds and frames, with 75 frames per second.
+const FRAMES_PER_SECOND: u64 = 75;
+
+/// What a CUE sheet tells about a single audio file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueFile {
+    /// the CUE sheet itself
+    pub sheet: PathBuf,
+    pub performer: Option<String>,
+    pub title: Option<String>,
+    /// title and start of each track inside the file
+    pub tracks: Vec<(String, Duration)>,
+}
+
+impl CueFile {
+    /// The tracks as chapters, the last one ending with the file. Tracks
+    /// out of order end where they start.
+    pub fn chapters(&self, duration: Duration) -> Vec<Chapter> {
+        self.tracks
+            .iter()
+            .enumerate()
+            .map(|(index, (title, start))| Chapter {
+                title: title.clone(),
+                start: *start,
+                end: self
+                    .tracks
+                    .get(index + 1)
+                    .map_or(duration, |(_, next)| *next)
+                    .max(*start),
+            })
+            .collect()
+    }
+
+    /// Fills in what the embedded tags leave empty. Embedded chapters are
+    /// kept, they belong to the file itself.
+    pub fn complete(&self, audio_info: &mut CommonAudioInfo) {
+        if audio_info.artist.is_empty() {
+            if let Some(performer) = &self.performer {
+                audio_info.artist = performer.clone();
+            }
+        }
+        if audio_info.title.is_empty() {
+            if let Some(title) = &self.title {
+                audio_info.title = title.clone();
+            }
+        }
+        if audio_info.album.as_ref().is_none_or(String::is_empty) {
+            audio_info.album = self.title.clone();
+        }
+        if audio_info
+            .album_artist
+            .as_ref()
+            .is_none_or(String::is_empty)
+        {
+            audio_info.album_artist = self.performer.clone();
+        }
+        if audio_info.chapters.as_ref().is_none_or(Vec::is_empty) && !self.tracks.is_empty() {
+            audio_info.chapters = Some(self.chapters(audio_info.duration));
+        }
+    }
+}
+
+/// The CUE sheets of a folder, by the audio file they refer to.
+#[derive(Default)]
+pub struct CueSheets {
+    files: HashMap<PathBuf, CueFile>,
+}
+
+impl CueSheets {
+    /// Reads the CUE sheets among the files of a folder.
+    pub fn of(paths: &[PathBuf]) -> Self {
+        let mut files = HashMap::new();
+        for sheet in paths.iter().filter(|path| is_cue_sheet(path)) {
+            let text = match fs::read(sheet) {
+                Ok(bytes) => decode(bytes),
+                Err(e) => {
+                    warn!("could not read cue sheet {:?}: {}", sheet, e);
+                    continue;
+                }
+            };
+            let dir = sheet.parent().unwrap_or_else(|| Path::new(""));
+            for (file_name, cue_file) in parse(sheet, &text) {
+                match referenced_file(dir, &file_name, paths) {
+                    Some(path) => {
+                        files.insert(path, cue_file);
+                    }
+                    None => warn!("cue sheet {:?} refers to missing {}", sheet, file_name),
+                }
+            }
+        }
+        Self { files }
+    }
+
+    /// What a CUE sheet tells about the audio file, if any sheet refers to it.
+    pub fn get(&self, path: &Path) -> Option<&CueFile> {
+        self.files.get(path)
+    }
+}
+
+fn is_cue_sheet(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("cue"))
+}
+
+/// Sheets (and playlists) are UTF-8, maybe with BOM, or Latin-1 from older tools.
+pub fn decode(bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(text) => text.trim_start_matches('\u{feff}').to_string(),
+        Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// The file as named by the sheet or, since rippers often keep the name of
+/// the original wav after encoding, a file of the folder with the same stem.
+fn referenced_file(dir: &Path, file_name: &str, paths: &[PathBuf]) -> Option<PathBuf> {
+    let named = dir.join(file_name);
+    if paths.contains(&named) {
+        return Some(named);
+    }
+    let stem = named.file_stem()?;
+    paths
+        .iter()
+        .find(|path| path.file_stem() == Some(stem) && !is_cue_sheet(path))
+        .cloned()
+}
+
+/// All FILE entries of the sheet with what the sheet tells about them.
+pub fn parse(sheet: &Path, text: &str) -> Vec<(String, CueFile)> {
+    let mut performer = None;
+    let mut title = None;
+    let mut files: Vec<(String, CueFile)> = vec![];
+    // title of the current track, until its start is known
+    let mut track: Option<Option<String>> = None;
+    for line in text.lines() {
+        let (command, rest) = split_word(line.trim());
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => {
+                files.push((
+                    split_value(rest).0,
+                    CueFile {
+                        sheet: sheet.to_path_buf(),
+                        performer: performer.clone(),
+                        title: title.clone(),
+                        tracks: vec![],
+                    },
+                ));
+                track = None;
+            }
+            "TRACK" => track = Some(None),
+            "PERFORMER" if track.is_none() && files.is_empty() => {
+                performer = Some(split_value(rest).0)
+            }
+            "TITLE" => match &mut track {
+                Some(track_title) => *track_title = Some(split_value(rest).0),
+                None if files.is_empty() => title = Some(split_value(rest).0),
+                None => (),
+            },
+            "INDEX" => {
+                // INDEX 01 is where the track starts, INDEX 00 its pregap
+                let (number, time) = split_word(rest);
+                if number.parse::<u8>() != Ok(1) {
+                    continue;
+                }
+                if let (Some(start), Some(track_title), Some((_, cue_file))) =
+                    (parse_time(time), track.take(), files.last_mut())
+                {
+                    let number = cue_file.tracks.len() + 1;
+                    cue_file.tracks.push((
+                        track_title.unwrap_or_else(|| format!("Track {:02}", number)),
+                        start,
+                    ));
+                }
+            }
+            _ => (),
+        }
+    }
+    files
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (text, ""),
+    }
+}
+
+/// A quoted value, or a single word without quotes.
+fn split_value(text: &str) -> (String, &str) {
+    if let Some(quoted) = text.strip_prefix('"') {
+        match quoted.split_once('"') {
+            Some((value, rest)) => (value.to_string(), rest.trim_start()),
+            None => (quoted.to_string(), ""),
+        }
+    } else {
+        let (value, rest) = split_word(text);
+        (value.to_string(), rest)
+    }
+}
+
+/// `mm:ss:ff`, minutes may exceed 59.
+fn parse_time(text: &str) -> Option<Duration> {
+    let parts: Vec<u64> = text
+        .trim()
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    match parts[..] {
+        [minutes, seconds, frames] if seconds < 60 && frames < FRAMES_PER_SECOND => Some(
+            Duration::from_secs(minutes * 60 + seconds)
+                + Duration::from_millis(frames * 1000 / FRAMES_PER_SECOND),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sheet() {
+        let text = "\u{feff}REM GENRE Audiobook\r\n\
+                    PERFORMER \"George Orwell\"\r\n\
+                    TITLE \"Animal Farm\"\r\n\
+                    FILE \"Animal Farm.wav\" WAVE\r\n  \
+                      TRACK 01 AUDIO\r\n    \
+                        TITLE \"Chapter I\"\r\n    \
+                        INDEX 01 00:00:00\r\n  \
+                      TRACK 02 AUDIO\r\n    \
+                        TITLE \"Chapter II\"\r\n    \
+                        INDEX 00 21:09:00\r\n    \
+                        INDEX 01 21:10:37\r\n  \
+                      TRACK 03 AUDIO\r\n    \
+                        INDEX 01 75:00:00\r\n";
+        let sheet = Path::new("/books/Animal Farm.cue");
+        let files = parse(sheet, &decode(text.as_bytes().to_vec()));
+        assert_eq!(files.len(), 1);
+        let (file_name, cue_file) = &files[0];
+        assert_eq!(file_name, "Animal Farm.wav");
+        assert_eq!(cue_file.performer.as_deref(), Some("George Orwell"));
+        assert_eq!(cue_file.title.as_deref(), Some("Animal Farm"));
+        assert_eq!(
+            cue_file.tracks,
+            vec![
+                ("Chapter I".to_string(), Duration::from_secs(0)),
+                ("Chapter II".to_string(), Duration::from_millis(1_270_493)),
+                ("Track 03".to_string(), Duration::from_secs(4500)),
+            ]
+        );
+        let chapters = cue_file.chapters(Duration::from_secs(5000));
+        assert_eq!(chapters[1].end, Duration::from_secs(4500));
+        assert_eq!(chapters[2].end, Duration::from_secs(5000));
+
+        // rippers keep the name of the wav, the folder has the flac
+        let paths = vec![
+            PathBuf::from("/books/Animal Farm.cue"),
+            PathBuf::from("/books/Animal Farm.flac"),
+        ];
+        assert_eq!(
+            referenced_file(Path::new("/books"), file_name, &paths),
+            Some(PathBuf::from("/books/Animal Farm.flac"))
+        );
+    }
+
+    #[test]
+    fn chapters_out_of_order() {
+        let cue_file = CueFile {
+            tracks: vec![
+                ("Two".to_string(), Duration::from_secs(20)),
+                ("One".to_string(), Duration::from_secs(10)),
+            ],
+            ..CueFile::default()
+        };
+        let chapters = cue_file.chapters(Duration::from_secs(5));
+        assert_eq!(chapters[0].end, Duration::from_secs(20));
+        assert_eq!(chapters[1].end, Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,75 @@
+//! Identifies files and folders independent of the path they were reached
+//! by (symlinks, hard links, bind mounts), to visit each of them only once.
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    fs::Metadata,
+    hash::{Hash, Hasher},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// The set of visited ids is split up, so the searching threads seldom wait
+/// for the same lock.
+static VISITED_SHARDS: usize = 64;
+
+/// Device and inode of a file or folder.
+#[cfg(unix)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FileId {
+    device: u64,
+    inode: u64,
+}
+
+#[cfg(unix)]
+impl FileId {
+    pub fn of(_path: &Path, metadata: &Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Some(Self {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+}
+
+/// Without inodes the canonical path has to do, which at least
+/// resolves symlinks (but no hard links).
+#[cfg(not(unix))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FileId {
+    path: std::path::PathBuf,
+}
+
+#[cfg(not(unix))]
+impl FileId {
+    pub fn of(path: &Path, _metadata: &Metadata) -> Option<Self> {
+        path.canonicalize().ok().map(|path| Self { path })
+    }
+}
+
+/// All files and folders visited during a run, shared by all searching threads.
+#[derive(Clone)]
+pub struct Visited {
+    shards: Arc<Vec<Mutex<HashSet<FileId>>>>,
+}
+
+impl Default for Visited {
+    fn default() -> Self {
+        Self {
+            shards: Arc::new(
+                (0..VISITED_SHARDS)
+                    .map(|_| Mutex::new(HashSet::new()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Visited {
+    /// True if it was not visited before (by any path).
+    pub fn first_visit(&self, id: FileId) -> bool {
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        let shard = hasher.finish() as usize % self.shards.len();
+        self.shards[shard].lock().unwrap().insert(id)
+    }
+}
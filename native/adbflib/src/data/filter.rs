@@ -0,0 +1,153 @@
+//! Decides which folders and files are searched at all: include and
+//! exclude globs (for all search paths or for single ones), `.adbfignore`
+//! files in gitignore syntax and folders marked by a `.nomedia` file.
+use super::super::common::config;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Include and exclude globs as given by the user. A glob without a `/`
+/// matches the name of a file or folder anywhere (like `@eaDir`), one with
+/// a `/` matches the path relative to the search path (like `podcasts/*`).
+#[derive(Clone, Debug, Default)]
+pub struct ScanRules {
+    /// if given, only matching files are searched (folders are always entered)
+    pub include: Vec<String>,
+    /// matching files and folders are not searched
+    pub exclude: Vec<String>,
+}
+
+/// The rules of a single search path, ready to be matched.
+#[derive(Clone)]
+pub struct PathFilter {
+    root: PathBuf,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    /// Combines all rules that apply to the search path.
+    pub fn new(root: &Path, rules: &[&ScanRules]) -> Self {
+        let include: Vec<&String> = rules.iter().flat_map(|rule| &rule.include).collect();
+        let exclude: Vec<&String> = rules.iter().flat_map(|rule| &rule.exclude).collect();
+        Self {
+            root: root.to_path_buf(),
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(glob_set(&include))
+            },
+            exclude: glob_set(&exclude),
+        }
+    }
+
+    /// False if the globs keep the file or folder out.
+    pub fn allows(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        if self.exclude.is_match(relative) {
+            return false;
+        }
+        is_dir
+            || self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.is_match(relative))
+    }
+}
+
+fn glob_set(patterns: &[&String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = if pattern.contains('/') {
+            pattern.trim_start_matches('/').to_string()
+        } else {
+            format!("**/{}", pattern)
+        };
+        match GlobBuilder::new(&pattern).literal_separator(true).build() {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => warn!("glob {} is not used: {}", pattern, e),
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        warn!("globs are not used: {}", e);
+        GlobSet::empty()
+    })
+}
+
+/// The `.adbfignore` files of a folder and of the folders above it.
+#[derive(Clone, Default)]
+pub struct IgnoreFiles {
+    stack: Vec<Arc<Gitignore>>,
+}
+
+impl IgnoreFiles {
+    /// The ignore files inside the folder, with the one of the folder if there is one.
+    pub fn enter(&self, dir: &Path) -> Self {
+        let ignore_file = dir.join(config::data::IGNORE_FILE);
+        let mut stack = self.stack.clone();
+        if ignore_file.is_file() {
+            let mut builder = GitignoreBuilder::new(dir);
+            if let Some(e) = builder.add(&ignore_file) {
+                warn!("{:?} is not fully used: {}", ignore_file, e);
+            }
+            match builder.build() {
+                Ok(gitignore) => stack.push(Arc::new(gitignore)),
+                Err(e) => warn!("{:?} is not used: {}", ignore_file, e),
+            }
+        }
+        Self { stack }
+    }
+
+    /// The ignore file closest to the path decides.
+    pub fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        for gitignore in self.stack.iter().rev() {
+            match gitignore.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => (),
+            }
+        }
+        false
+    }
+}
+
+/// A folder with a `.nomedia` file has no media to be searched.
+pub fn has_no_media(dir: &Path) -> bool {
+    dir.join(config::data::NO_MEDIA_FILE).exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_and_exclude() {
+        let global = ScanRules {
+            include: vec!["*.mp3".to_string(), "*.m4b".to_string()],
+            exclude: vec!["@eaDir".to_string()],
+        };
+        let per_path = ScanRules {
+            include: vec![],
+            exclude: vec!["podcasts/*".to_string()],
+        };
+        let filter = PathFilter::new(Path::new("/books"), &[&global, &per_path]);
+        assert!(filter.allows(Path::new("/books/Orwell/1984.mp3"), false));
+        assert!(!filter.allows(Path::new("/books/Orwell/cover.jpg"), false));
+        assert!(filter.allows(Path::new("/books/Orwell"), true));
+        assert!(!filter.allows(Path::new("/books/Orwell/@eaDir"), true));
+        assert!(!filter.allows(Path::new("/books/podcasts/news.mp3"), false));
+        // only relative to the search path
+        assert!(filter.allows(Path::new("/books/old/podcasts/news.mp3"), false));
+
+        let nothing = PathFilter::new(Path::new("/books"), &[]);
+        assert!(nothing.allows(Path::new("/books/Orwell/cover.jpg"), false));
+    }
+}
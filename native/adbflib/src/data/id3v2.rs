@@ -0,0 +1,333 @@
+//! Reads the chapters (CHAP frames, ordered by the CTOC frame) and the cover
+//! (APIC frame) of an ID3v2 tag, which the id3 crate does not support yet.
+use super::{audio_info::Chapter, mpeg::synchsafe};
+use std::{
+    collections::HashMap,
+    io::{self, Read, Seek, SeekFrom},
+    time::Duration,
+};
+
+/// A frame with its id and its content.
+struct Frame<'a> {
+    id: &'a [u8],
+    content: &'a [u8],
+}
+
+/// Iterates over the frames of a tag or of the sub frames of a frame.
+struct Frames<'a> {
+    data: &'a [u8],
+    version: u8,
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Frame<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // frame ids are upper case letters or digits, anything else is padding
+        if self.data.len() < 10 || !self.data[0].is_ascii_alphanumeric() {
+            return None;
+        }
+        let size = if self.version == 4 {
+            synchsafe(&self.data[4..8])
+        } else {
+            big_endian(&self.data[4..8])
+        } as usize;
+        if size > self.data.len() - 10 {
+            return None;
+        }
+        let (frame, rest) = self.data.split_at(10 + size);
+        self.data = rest;
+        Some(Frame {
+            id: &frame[0..4],
+            content: &frame[10..],
+        })
+    }
+}
+
+/// The picture type of the front cover.
+const FRONT_COVER: u8 = 3;
+
+/// Reads the chapters of the ID3v2 tag at the beginning of the file.
+pub fn read_chapters<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<Chapter>> {
+    Ok(match read_frames(reader)? {
+        Some((frames, version)) => chapters_of(Frames {
+            data: &frames,
+            version,
+        }),
+        None => vec![],
+    })
+}
+
+/// Reads the front cover of the ID3v2 tag, or its first picture if none is
+/// marked as front cover.
+pub fn read_cover<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let (frames, version) = match read_frames(reader)? {
+        Some(frames) => frames,
+        None => return Ok(None),
+    };
+    let mut cover = None;
+    for frame in (Frames {
+        data: &frames,
+        version,
+    }) {
+        if frame.id == b"APIC" {
+            if let Some((picture_type, data)) = read_apic(frame.content) {
+                if picture_type == FRONT_COVER {
+                    return Ok(Some(data.to_vec()));
+                }
+                cover = cover.or_else(|| Some(data.to_vec()));
+            }
+        }
+    }
+    Ok(cover)
+}
+
+/// The frames of the tag with its version, none for tags before ID3v2.3
+/// (which have other frame ids).
+fn read_frames<R: Read + Seek>(reader: &mut R) -> io::Result<Option<(Vec<u8>, u8)>> {
+    let mut header = [0u8; 10];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut header)?;
+    if &header[0..3] != b"ID3" {
+        return Err(invalid_data("no ID3v2 tag"));
+    }
+    let version = header[3];
+    // chapters came with ID3v2.3
+    if version < 3 {
+        return Ok(None);
+    }
+    let mut tag = vec![0u8; synchsafe(&header[6..10]) as usize];
+    reader.read_exact(&mut tag)?;
+    let flags = header[5];
+    if flags & 0x80 != 0 {
+        tag = remove_unsynchronisation(&tag);
+    }
+    if flags & 0x40 != 0 && tag.len() >= 4 {
+        let extended_size = if version == 4 {
+            synchsafe(&tag[0..4]) as usize
+        } else {
+            big_endian(&tag[0..4]) as usize + 4
+        };
+        tag.drain(..extended_size.min(tag.len()));
+    }
+    Ok(Some((tag, version)))
+}
+
+/// Collects all CHAP frames, in the order of the top level CTOC frame,
+/// or if there is none in the order of their start.
+fn chapters_of(frames: Frames<'_>) -> Vec<Chapter> {
+    let version = frames.version;
+    let mut chapters = HashMap::new();
+    let mut order = None;
+    for frame in frames {
+        match frame.id {
+            b"CHAP" => {
+                if let Some((element_id, chapter)) = read_chap(frame.content, version) {
+                    chapters.insert(element_id, chapter);
+                }
+            }
+            b"CTOC" => {
+                if let Some((is_top_level, children)) = read_ctoc(frame.content) {
+                    if is_top_level || order.is_none() {
+                        order = Some(children);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    match order {
+        Some(children) if !children.is_empty() => children
+            .iter()
+            .filter_map(|element_id| chapters.remove(element_id))
+            .collect(),
+        _ => {
+            let mut chapters: Vec<Chapter> = chapters.into_values().collect();
+            chapters.sort_by_key(|chapter| chapter.start);
+            chapters
+        }
+    }
+}
+
+/// Element id, start and end time (ms), start and end offset, then sub frames.
+fn read_chap(content: &[u8], version: u8) -> Option<(Vec<u8>, Chapter)> {
+    let (element_id, rest) = split_terminated(content)?;
+    let start = big_endian(rest.get(0..4)?);
+    let end = big_endian(rest.get(4..8)?);
+    let title = Frames {
+        data: rest.get(16..)?,
+        version,
+    }
+    .find(|frame| frame.id == b"TIT2")
+    .map(|frame| read_text(frame.content))
+    .unwrap_or_default();
+    Some((
+        element_id.to_vec(),
+        Chapter {
+            title,
+            start: Duration::from_millis(start as u64),
+            end: Duration::from_millis(end.max(start) as u64),
+        },
+    ))
+}
+
+/// Element id, flags, entry count and the element ids of the children.
+fn read_ctoc(content: &[u8]) -> Option<(bool, Vec<Vec<u8>>)> {
+    let (_, rest) = split_terminated(content)?;
+    let is_top_level = rest.first()? & 0x01 != 0;
+    let mut entries = rest.get(2..)?;
+    let mut children = vec![];
+    for _ in 0..*rest.get(1)? {
+        let (child, next) = split_terminated(entries)?;
+        children.push(child.to_vec());
+        entries = next;
+    }
+    Some((is_top_level, children))
+}
+
+/// Encoding, mime type, picture type, description, then the image itself.
+fn read_apic(content: &[u8]) -> Option<(u8, &[u8])> {
+    let (&encoding, rest) = content.split_first()?;
+    let (_mime_type, rest) = split_terminated(rest)?;
+    let (&picture_type, rest) = rest.split_first()?;
+    let (_description, data) = if encoding == 1 || encoding == 2 {
+        split_terminated_utf16(rest)?
+    } else {
+        split_terminated(rest)?
+    };
+    Some((picture_type, data))
+}
+
+/// Text frames start with their encoding.
+fn read_text(content: &[u8]) -> String {
+    let text = match content.split_first() {
+        Some((&1, text)) if text.starts_with(&[0xFF, 0xFE]) => utf16(&text[2..], false),
+        Some((&1, text)) if text.starts_with(&[0xFE, 0xFF]) => utf16(&text[2..], true),
+        Some((&2, text)) => utf16(text, true),
+        Some((&3, text)) => String::from_utf8_lossy(text).to_string(),
+        Some((_, text)) => text.iter().map(|&byte| byte as char).collect(),
+        None => String::new(),
+    };
+    text.trim_end_matches('\0').to_string()
+}
+
+fn utf16(data: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| {
+            if big_endian {
+                (pair[0] as u16) << 8 | pair[1] as u16
+            } else {
+                (pair[1] as u16) << 8 | pair[0] as u16
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Splits at the first zero byte, which is dropped.
+fn split_terminated(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.iter().position(|&byte| byte == 0)?;
+    Some((&data[..end], &data[end + 1..]))
+}
+
+/// Splits at the first (aligned) zero of UTF-16 text, which is dropped.
+fn split_terminated_utf16(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.chunks_exact(2).position(|pair| pair == [0, 0])? * 2;
+    Some((&data[..end], &data[end + 2..]))
+}
+
+/// Every 0xFF 0x00 was written for a single 0xFF.
+fn remove_unsynchronisation(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut last = 0u8;
+    for &byte in data {
+        if !(last == 0xFF && byte == 0x00) {
+            result.push(byte);
+        }
+        last = byte;
+    }
+    result
+}
+
+fn big_endian(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 8) | *byte as u32)
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn frame(id: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend_from_slice(&(content.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(content);
+        frame
+    }
+
+    fn chap(element_id: &str, start: u32, end: u32, title: &str) -> Vec<u8> {
+        let mut content = element_id.as_bytes().to_vec();
+        content.push(0);
+        for value in &[start, end, u32::MAX, u32::MAX] {
+            content.extend_from_slice(&value.to_be_bytes());
+        }
+        let mut text = vec![3];
+        text.extend_from_slice(title.as_bytes());
+        content.extend(frame(b"TIT2", &text));
+        frame(b"CHAP", &content)
+    }
+
+    #[test]
+    fn id3v2_chapters() {
+        let ctoc = frame(b"CTOC", b"toc\x00\x03\x02ch1\x00ch0\x00");
+        let frames = [
+            frame(b"TIT2", b"\x00Animal Farm"),
+            chap("ch0", 0, 60_000, "Kapitel 1"),
+            chap("ch1", 60_000, 150_500, "Kapitel 2"),
+            ctoc,
+            vec![0u8; 20],
+        ]
+        .concat();
+        // v2.3, sizes of frames are plain
+        let mut data = b"ID3\x03\x00\x00".to_vec();
+        let size = frames.len() as u32;
+        data.extend_from_slice(&[
+            (size >> 21) as u8 & 0x7F,
+            (size >> 14) as u8 & 0x7F,
+            (size >> 7) as u8 & 0x7F,
+            size as u8 & 0x7F,
+        ]);
+        data.extend(frames);
+
+        let chapters = read_chapters(&mut Cursor::new(data.clone())).unwrap();
+        assert_eq!(chapters.len(), 2);
+        // in order of the table of contents
+        assert_eq!(chapters[0].title, "Kapitel 2");
+        assert_eq!(chapters[0].end, Duration::from_millis(150_500));
+        assert_eq!(chapters[1].title, "Kapitel 1");
+        assert_eq!(chapters[1].start, Duration::from_secs(0));
+        assert_eq!(read_cover(&mut Cursor::new(data)).unwrap(), None);
+    }
+
+    #[test]
+    fn id3v2_cover() {
+        // a back cover with UTF-16 description, then the front cover
+        let back = frame(b"APIC", b"\x01image/png\x00\x04\xFF\xFEb\x00\x00\x00back");
+        let front = frame(b"APIC", b"\x00image/jpeg\x00\x03front\x00\xFF\xD8\xFF");
+        let frames = [back, front].concat();
+        let mut data = b"ID3\x04\x00\x00\x00\x00".to_vec();
+        data.extend_from_slice(&[(frames.len() >> 7) as u8, frames.len() as u8 & 0x7F]);
+        data.extend(frames);
+
+        let cover = read_cover(&mut Cursor::new(data)).unwrap();
+        assert_eq!(cover, Some(b"\xFF\xD8\xFF".to_vec()));
+    }
+}
@@ -1,9 +1,6 @@
 //! IPC module will hold all massive (that is why IPC) internal messages
 //! which occur due to data collection, its start and its end.
-use super::{
-    audio_info::{AudioInfo, AudioInfoKey},
-    IFInternalCollectionOutputData,
-};
+use super::{audio_book::AudioBook, audio_info::AudioInfoKey, IFInternalCollectionOutputData};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct IFCollectionOutputData {
@@ -27,5 +24,5 @@ impl IFCollectionOutputData {
 #[derive(Serialize, Deserialize, Debug)]
 pub enum IPC {
     DoneSearching(IFCollectionOutputData),
-    PublishSingleAudioDataRecord(AudioInfoKey, AudioInfo),
+    PublishAudioBookRecord(AudioInfoKey, AudioBook),
 }
@@ -0,0 +1,277 @@
+//! Tells audiobooks from music and podcasts, which are often kept in the
+//! same folders. Each hint of the tags and the folders below the search path
+//! adds to a score, an undecided book is taken as audiobook.
+use super::audio_book::AudioBook;
+use std::{path::Path, time::Duration};
+
+/// Genres of books, also of the ID3v1 genre list ("Speech").
+static AUDIOBOOK_GENRES: [&str; 10] = [
+    "audiobook",
+    "audiobooks",
+    "audio book",
+    "hörbuch",
+    "hörspiel",
+    "spoken",
+    "speech",
+    "literature",
+    "lesung",
+    "books",
+];
+static MUSIC_GENRES: [&str; 24] = [
+    "rock",
+    "pop",
+    "jazz",
+    "metal",
+    "classical",
+    "electronic",
+    "hip-hop",
+    "hip hop",
+    "rap",
+    "blues",
+    "country",
+    "folk",
+    "soundtrack",
+    "dance",
+    "techno",
+    "house",
+    "punk",
+    "reggae",
+    "soul",
+    "r&b",
+    "indie",
+    "alternative",
+    "disco",
+    "schlager",
+];
+static PODCAST_WORDS: [&str; 2] = ["podcast", "episode"];
+/// Words in titles, albums and folders of books.
+static AUDIOBOOK_WORDS: [&str; 15] = [
+    "chapter",
+    "kapitel",
+    "chapitre",
+    "capítulo",
+    "unabridged",
+    "abridged",
+    "ungekürzt",
+    "gekürzt",
+    "audiobook",
+    "audio book",
+    "hörbuch",
+    "hörbücher",
+    "hörspiel",
+    "read by",
+    "gelesen von",
+];
+static MUSIC_WORDS: [&str; 9] = [
+    "remix",
+    "remaster",
+    "remastered",
+    "feat",
+    "live at",
+    "radio edit",
+    "instrumental",
+    "music",
+    "musik",
+];
+
+/// Tracks that long are rarely songs, tracks that short rarely chapters.
+static LONG_TRACK: Duration = Duration::from_secs(20 * 60);
+static SHORT_TRACK: Duration = Duration::from_secs(7 * 60);
+/// Albums are shorter, books longer.
+static LONG_BOOK: Duration = Duration::from_secs(3 * 60 * 60);
+static SHORT_BOOK: Duration = Duration::from_secs(80 * 60);
+/// Albums rarely come on that many discs, and with that many tracks.
+static MANY_DISCS: u16 = 4;
+static MANY_TRACKS: usize = 30;
+
+/// What kind of recording a book is.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookKind {
+    AudioBook,
+    Music,
+    Podcast,
+}
+
+/// Scores the hints for the book: above zero for an audiobook, below for
+/// music. Podcasts are told by their own hints.
+pub fn of(audio_book: &AudioBook) -> BookKind {
+    let tracks = &audio_book.tracks;
+    let mut score = 0;
+    let mut podcast = 0;
+
+    let genres: Vec<String> = tracks
+        .iter()
+        .filter_map(|track| track.genre.as_ref())
+        .map(|genre| genre.to_lowercase())
+        .collect();
+    if genres
+        .iter()
+        .any(|genre| has_words(genre, &AUDIOBOOK_GENRES))
+    {
+        score += 3;
+    } else if genres.iter().any(|genre| has_words(genre, &MUSIC_GENRES)) {
+        score -= 3;
+    }
+    if genres.iter().any(|genre| genre.contains("podcast")) {
+        podcast += 3;
+    }
+
+    if !tracks.is_empty() {
+        let average = audio_book.duration / tracks.len() as u32;
+        if average >= LONG_TRACK {
+            score += 2;
+        } else if average < SHORT_TRACK {
+            score -= 1;
+        }
+    }
+    if audio_book.duration >= LONG_BOOK {
+        score += 2;
+    } else if audio_book.duration < SHORT_BOOK {
+        score -= 1;
+    }
+    let discs = tracks
+        .iter()
+        .filter_map(|track| track.total_discs.max(track.disc))
+        .chain(audio_book.total_discs)
+        .max()
+        .unwrap_or(0);
+    if discs >= MANY_DISCS {
+        score += 2;
+    }
+    if tracks.len() >= MANY_TRACKS {
+        score += 1;
+    }
+    // only books have chapters inside their files, narrators and ISBNs
+    if tracks.iter().any(|track| {
+        track
+            .chapters
+            .as_ref()
+            .is_some_and(|chapters| !chapters.is_empty())
+            || track.narrator.is_some()
+            || track.isbn.is_some()
+            || track.series.is_some()
+    }) {
+        score += 3;
+    }
+
+    // the words of the folders below the search path (like
+    // "Hörbücher/Author/Title") and the tags
+    let folder = Path::new(&audio_book.folder);
+    let texts: Vec<String> = folder
+        .strip_prefix(&audio_book.root)
+        .unwrap_or(folder)
+        .iter()
+        .filter_map(|folder| folder.to_str())
+        .chain(tracks.iter().filter_map(|track| track.album.as_deref()))
+        .chain(tracks.iter().map(|track| track.title.as_str()))
+        .map(|text| text.to_lowercase())
+        .collect();
+    if texts.iter().any(|text| has_words(text, &AUDIOBOOK_WORDS)) {
+        score += 2;
+    }
+    if texts.iter().any(|text| has_words(text, &MUSIC_WORDS)) {
+        score -= 2;
+    }
+    if texts.iter().any(|text| has_words(text, &PODCAST_WORDS)) {
+        podcast += 2;
+    }
+
+    trace!(
+        "kind of '{}': book score {}, podcast score {}",
+        audio_book.folder,
+        score,
+        podcast
+    );
+    if podcast >= 3 {
+        BookKind::Podcast
+    } else if score >= 0 {
+        BookKind::AudioBook
+    } else {
+        BookKind::Music
+    }
+}
+
+/// Matched by whole words, "Biography" is no "rap" and "Musician" no "music".
+fn has_words(text: &str, phrases: &[&str]) -> bool {
+    let text = words(text);
+    phrases.iter().any(|phrase| {
+        let phrase = words(phrase);
+        text.windows(phrase.len())
+            .any(|window| window == phrase.as_slice())
+    })
+}
+
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::audio_info::AudioInfo;
+
+    fn book(folder: &str, genre: &str, minutes: &[u64], title: &str) -> AudioBook {
+        let tracks: Vec<AudioInfo> = minutes
+            .iter()
+            .enumerate()
+            .map(|(index, minutes)| AudioInfo {
+                duration: Duration::from_secs(minutes * 60),
+                file_name: format!("{}/{:02}.mp3", folder, index + 1),
+                artist: "Artist".to_string(),
+                title: format!("{} {}", title, index + 1),
+                track: Some(index as u16 + 1),
+                genre: Some(genre.to_string()).filter(|genre| !genre.is_empty()),
+                ..AudioInfo::default()
+            })
+            .collect();
+        AudioBook {
+            author: "Artist".to_string(),
+            title: title.to_string(),
+            folder: folder.to_string(),
+            root: String::new(),
+            disc: None,
+            total_discs: None,
+            duration: tracks.iter().map(|track| track.duration).sum(),
+            tracks,
+            series: None,
+            volume: None,
+        }
+    }
+
+    #[test]
+    fn tell_books_from_music() {
+        let album = book("/music/Band/Album", "Rock", &[4, 3, 5, 4, 6, 3], "Song");
+        assert_eq!(of(&album), BookKind::Music);
+        // no genre, but long files in a folder of books
+        let novel = book("/Hörbücher/Orwell/1984", "", &[62, 58, 71, 65], "Teil");
+        assert_eq!(of(&novel), BookKind::AudioBook);
+        // short tracks of a CD rip, told by genre and titles
+        let rip = book("/rips/Animal Farm", "Audiobook", &[3; 20], "Chapter");
+        assert_eq!(of(&rip), BookKind::AudioBook);
+        let show = book("/downloads/Tech Talk", "Podcast", &[45, 52], "Episode");
+        assert_eq!(of(&show), BookKind::Podcast);
+        // nothing known, the book is kept
+        let unknown = book("/a/b", "", &[30], "Track");
+        assert_eq!(of(&unknown), BookKind::AudioBook);
+        // genres are no music because of "rap" or "pop" inside their words
+        let memoir = book("/a/b", "Biography & Memoir", &[30], "Track");
+        assert_eq!(of(&memoir), BookKind::AudioBook);
+        let science = book("/a/b", "Popular Science", &[30], "Track");
+        assert_eq!(of(&science), BookKind::AudioBook);
+        let hip_hop = book("/a/b", "Hip-Hop/Rap", &[30], "Track");
+        assert_eq!(of(&hip_hop), BookKind::Music);
+    }
+
+    #[test]
+    fn words_below_the_search_path() {
+        let mut novel = book("/home/me/Music/Orwell/Animal Farm", "", &[30], "Track");
+        assert_eq!(of(&novel), BookKind::Music);
+        novel.root = "/home/me/Music".to_string();
+        assert_eq!(of(&novel), BookKind::AudioBook);
+        // whole words only
+        let biography = book("/a/b", "", &[30], "The Musician");
+        assert_eq!(of(&biography), BookKind::AudioBook);
+    }
+}
@@ -0,0 +1,140 @@
+//! The metrics to compare keys of books with, and the tolerance up to
+//! which keys count as similar. The bktree search depends on the triangle
+//! inequality: Levenshtein based token-set and normalized distances keep it,
+//! the OSA distance does not (a swap next to an edit may count less than the
+//! detour), so a search by it may miss a few keys at the edge of the tolerance.
+use super::{
+    audio_info::AudioInfoKey,
+    bktree::{levenshtein_distance, osa_distance, Metric},
+};
+
+/// The normalized distance is given in percent.
+static NORMALIZED_SCALE: f64 = 100.0;
+
+/// How the distance between two keys is measured.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum KeyMetric {
+    /// edits (with swaps) of characters, the key as it is; not a metric
+    #[default]
+    Osa,
+    /// edits of the sorted distinct words, so word order does not matter
+    TokenSet,
+    /// edits in relation to the length of both keys
+    Normalized,
+}
+
+impl KeyMetric {
+    /// Parses the name used on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "osa" => Some(KeyMetric::Osa),
+            "token-set" => Some(KeyMetric::TokenSet),
+            "normalized" => Some(KeyMetric::Normalized),
+            _ => None,
+        }
+    }
+
+    pub fn distance(self) -> Metric<AudioInfoKey> {
+        match self {
+            KeyMetric::Osa => |a, b| osa_distance(a.get(), b.get()),
+            KeyMetric::TokenSet => |a, b| token_set_distance(a.get(), b.get()),
+            KeyMetric::Normalized => |a, b| normalized_distance(a.get(), b.get()),
+        }
+    }
+}
+
+/// The metric and its tolerance relative to the length of a key.
+#[derive(Clone, Debug)]
+pub struct Similarity {
+    pub metric: KeyMetric,
+    /// part of the key that may differ (0.15 is about 5 of 30 characters)
+    pub tolerance: f64,
+}
+
+impl Default for Similarity {
+    fn default() -> Self {
+        Self {
+            metric: KeyMetric::default(),
+            tolerance: 0.15,
+        }
+    }
+}
+
+impl Similarity {
+    /// The distance up to which other keys are similar to the key (rounded).
+    pub fn tolerance_of(&self, key: &AudioInfoKey) -> usize {
+        let tolerance = self.tolerance.max(0.0);
+        match self.metric {
+            KeyMetric::Osa | KeyMetric::TokenSet => {
+                (key.get().chars().count() as f64 * tolerance).round() as usize
+            }
+            KeyMetric::Normalized => (NORMALIZED_SCALE * tolerance).round() as usize,
+        }
+    }
+}
+
+/// The distinct words, sorted.
+fn token_set(text: &str) -> String {
+    let mut tokens: Vec<&str> = text.split_whitespace().collect();
+    tokens.sort_unstable();
+    tokens.dedup();
+    tokens.join(" ")
+}
+
+pub fn token_set_distance(a: &str, b: &str) -> usize {
+    levenshtein_distance(&token_set(a), &token_set(b))
+}
+
+/// The normalized Levenshtein distance 2d/(|a|+|b|+d) (by Yujian and Bo),
+/// which unlike d/max(|a|,|b|) is a metric; rounded up to whole percent,
+/// which keeps the triangle inequality.
+pub fn normalized_distance(a: &str, b: &str) -> usize {
+    let distance = levenshtein_distance(a, b);
+    if distance == 0 {
+        return 0;
+    }
+    let lengths = a.chars().count() + b.chars().count();
+    let normalized = 2.0 * distance as f64 / (lengths + distance) as f64;
+    (normalized * NORMALIZED_SCALE).ceil() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_order_does_not_matter() {
+        assert_eq!(token_set_distance("orwell george", "george orwell"), 0);
+        assert_eq!(token_set_distance("orwell george", "george orwel"), 1);
+        assert!(osa_distance("orwell george", "george orwell") > 5);
+    }
+
+    #[test]
+    fn normalized_by_length() {
+        assert_eq!(normalized_distance("abc", "abc"), 0);
+        assert_eq!(normalized_distance("abc", "xyz"), 67);
+        assert_eq!(normalized_distance("", "abc"), 100);
+        // the same edit weighs less in a longer key
+        assert!(
+            normalized_distance("the magic mountain", "the magic mountian")
+                < normalized_distance("magic", "magci")
+        );
+    }
+
+    #[test]
+    fn tolerance_relative_to_key() {
+        let similarity = Similarity::default();
+        let short = AudioInfoKey::new(&"orwell".to_string(), &"1984".to_string());
+        let long = AudioInfoKey::new(
+            &"thomas mann".to_string(),
+            &"der zauberberg erster teil".to_string(),
+        );
+        assert_eq!(similarity.tolerance_of(&short), 2);
+        assert_eq!(similarity.tolerance_of(&long), 6);
+        let normalized = Similarity {
+            metric: KeyMetric::Normalized,
+            tolerance: 0.15,
+        };
+        assert_eq!(normalized.tolerance_of(&short), 15);
+    }
+}
@@ -1,9 +1,31 @@
 //! The oldest module, the data module stores all the data needed to collect
 //! and also the search, dir algorithms
+mod archive;
+pub mod audio_book;
 pub mod audio_info;
 mod bktree;
+mod cache;
+pub mod classify;
 pub mod collection;
+pub mod cover;
+mod cue;
+mod file_id;
+pub mod filter;
+mod id3v2;
 pub mod ipc;
+pub mod kind;
+pub mod metric;
+mod mp4;
+mod mpeg;
+pub mod normalize;
+mod ogg;
+pub mod options;
+mod payload;
+mod playlist;
+pub mod quality;
+pub mod report;
+mod series;
+mod sidecar;
 mod tag_readers;
 
 use self::{audio_info::Container, collection::Collection, ipc::IPC};
@@ -71,17 +93,22 @@ pub fn search_in_single_path(
             })
             .unwrap_or_else(|_| error!("... that should not happen here at start"));
     }
-    // this collection lock is fine, it's not the main Container, only some data
-    let locked_collection = &mut *collection_protected.lock().unwrap();
+    // file names are the keys of the cache, so they are absolute
+    let root = cache::canonical(Path::new(elem));
+    // the collection is only locked to get what the search needs ...
+    let visitor = collection_protected.lock().unwrap().visitor(&root);
 
     // do it: main task here is to visit and dive deep
     //        into the subfolders of this folder
-    match locked_collection.visit_path(
-        collection_data,
-        Path::new(elem),
-        &collection::Collection::visit_files,
-    ) {
-        Ok(local_stats) => {
+    match visitor.visit_path(&root) {
+        Ok(harvest) => {
+            // ... and to take over the results: all tracks of this path are
+            // known, so its books are complete
+            let local_stats =
+                collection_protected
+                    .lock()
+                    .unwrap()
+                    .take_harvest(collection_data, &root, harvest);
             if has_ui {
                 // send stop animation for that path
                 trace!("send stopAnimation for path {:?}", index);
@@ -105,12 +132,21 @@ pub fn search_in_single_path(
                     "\n\
                              analyzed: {an:>width$}, faulty: {fa:>width$}\n\
                              duplicates: {du:>width$}\n\
-                             searched: {se:>width$}, other: {ot:>width$}",
+                             searched: {se:>width$}, other: {ot:>width$}\n\
+                             playlists: {pl:>width$}, broken: {br:>width$}\n\
+                             archives: {ar:>width$}, skipped: {sk:>width$}\n\
+                             links: {li:>width$}, loops: {lo:>width$}",
                     an = local_stats.analyzed,
                     fa = local_stats.faulty,
                     du = local_stats.duplicates,
                     se = local_stats.searched,
                     ot = local_stats.other,
+                    pl = local_stats.playlists,
+                    br = local_stats.broken,
+                    ar = local_stats.archives,
+                    sk = local_stats.skipped,
+                    li = local_stats.links,
+                    lo = local_stats.cycles,
                     width = 3
                 );
                 println!("[{:?}] done {}", index, text);
@@ -154,11 +190,11 @@ pub fn publish_local_storage(container_handle: Arc<Mutex<Container>>, ipc_sender
     // send to ipc
     //
     let container = container_handle.lock().unwrap();
-    for (key, audio_info) in container.flush() {
+    for (key, audio_book) in container.flush() {
         ipc_sender
-            .send(IPC::PublishSingleAudioDataRecord(
+            .send(IPC::PublishAudioBookRecord(
                 key.clone(),
-                *audio_info.clone(),
+                *audio_book.clone(),
             ))
             .unwrap_or_else(|e| warn!("something went very wrong {}!", e));
     }
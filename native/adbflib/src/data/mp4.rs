@@ -0,0 +1,598 @@
+//! Reads the chapters of MP4 files (m4b/m4a audio books). Chapters are either
+//! stored as Nero chapter list (`moov/udta/chpl`) or as QuickTime chapter
+//! track, a text track referenced by `tref/chap` of the audio track.
+//! The cover is the first `data` atom of `moov/udta/meta/ilst/covr`, the
+//! quality is told by the sample description (`stsd`) of the sound track.
+use super::{
+    audio_info::Chapter,
+    quality::{Codec, Quality},
+};
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    time::Duration,
+};
+
+/// A moov atom bigger than this is not read into memory.
+static MOOV_SIZE_MAX: u64 = 64 * 1024 * 1024;
+/// Chapter tracks with more samples are broken, the counts come from the file.
+static CHAPTER_SAMPLES_MAX: usize = 10_000;
+
+/// Iterates over the atoms inside a parent atom's body.
+struct Atoms<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Atoms<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < 8 {
+            return None;
+        }
+        let (header_size, size) = match big_endian(&self.data[0..4]) {
+            0 => (8, self.data.len() as u64),
+            1 if self.data.len() >= 16 => (16, big_endian(&self.data[8..16])),
+            size => (8, size),
+        };
+        if size < header_size || size > self.data.len() as u64 {
+            return None;
+        }
+        let (atom, rest) = self.data.split_at(size as usize);
+        self.data = rest;
+        Some((&atom[4..8], &atom[header_size as usize..]))
+    }
+}
+
+fn atoms(data: &[u8]) -> Atoms<'_> {
+    Atoms { data }
+}
+
+/// The body of the first atom on the given path.
+fn child<'a>(data: &'a [u8], path: &[&[u8]]) -> Option<&'a [u8]> {
+    match path.split_first() {
+        None => Some(data),
+        Some((name, rest)) => atoms(data)
+            .find(|(atom_name, _)| atom_name == name)
+            .and_then(|(_, body)| child(body, rest)),
+    }
+}
+
+/// Reads the chapters, an empty list if there are none. The titles of a
+/// chapter track are read from the file, outside of the moov atom.
+pub fn read_chapters<R: Read + Seek>(reader: &mut R, moov: &[u8]) -> io::Result<Vec<Chapter>> {
+    let total = child(moov, &[b"mvhd"])
+        .and_then(read_time_header)
+        .map(|(timescale, duration)| scaled(duration, timescale))
+        .unwrap_or(Duration::from_secs(0));
+
+    let starts = match child(moov, &[b"udta", b"chpl"]).and_then(read_nero_chapters) {
+        Some(starts) if !starts.is_empty() => starts,
+        _ => read_chapter_track(reader, moov)?,
+    };
+    Ok(with_ends(starts, total))
+}
+
+/// Reads the cover, none if there is none.
+pub fn read_cover(moov: &[u8]) -> Option<Vec<u8>> {
+    // meta is a full atom, its children follow version and flags
+    child(moov, &[b"udta", b"meta"])
+        .and_then(|meta| meta.get(4..))
+        .and_then(|meta| child(meta, &[b"ilst", b"covr", b"data"]))
+        // the data atom starts with its type and locale
+        .and_then(|data| data.get(8..))
+        .filter(|image| !image.is_empty())
+        .map(|image| image.to_vec())
+}
+
+/// Reads codec, sample rate, channels and bitrate of the sound track.
+pub fn read_quality(moov: &[u8]) -> Option<Quality> {
+    let sound_track = atoms(moov)
+        .filter(|(name, _)| name == b"trak")
+        .map(|(_, body)| body)
+        .find(|track| {
+            child(track, &[b"mdia", b"hdlr"])
+                .and_then(|hdlr| hdlr.get(8..12))
+                .is_some_and(|handler| handler == b"soun")
+        });
+    sound_track
+        .and_then(|track| child(track, &[b"mdia", b"minf", b"stbl", b"stsd"]))
+        // a full atom with the entry count, then the sample entries
+        .and_then(|stsd| stsd.get(8..))
+        .and_then(|entries| atoms(entries).next())
+        .and_then(|(format, entry)| read_sample_entry(format, entry))
+}
+
+/// An audio sample entry: reserved and data reference (8), version, revision
+/// and vendor (8), channels, sample size, compression and packet size (8),
+/// the sample rate as 16.16 fixed point number, then its child atoms.
+fn read_sample_entry(format: &[u8], entry: &[u8]) -> Option<Quality> {
+    let channels = big_endian(entry.get(16..18)?) as u8;
+    let sample_rate = big_endian(entry.get(24..26)?) as u32;
+    let quality = |codec| Some(Quality::new(codec, 0, None, sample_rate, channels));
+    match format {
+        b"alac" => quality(Codec::Alac),
+        b"fLaC" => quality(Codec::Flac),
+        b"Opus" => quality(Codec::Opus),
+        b"mp4a" => {
+            // the elementary stream descriptor tells codec and bitrate
+            let esds = child(entry.get(28..)?, &[b"esds"]).and_then(|esds| esds.get(4..));
+            match esds.and_then(read_decoder_config) {
+                Some((codec, max_bitrate, avg_bitrate)) => Some(Quality::new(
+                    codec,
+                    (avg_bitrate / 1000) as u32,
+                    Some(max_bitrate != avg_bitrate).filter(|_| avg_bitrate > 0),
+                    sample_rate,
+                    channels,
+                )),
+                None => quality(Codec::Aac),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Codec, maximum and average bitrate of the decoder config descriptor
+/// inside the ES descriptor.
+fn read_decoder_config(esds: &[u8]) -> Option<(Codec, u64, u64)> {
+    let es = descriptor(esds, 0x03)?;
+    let flags = *es.get(2)?;
+    let mut position = 3;
+    // stream dependence, url and OCR stream are optional
+    if flags & 0x80 != 0 {
+        position += 2;
+    }
+    if flags & 0x40 != 0 {
+        position += 1 + *es.get(position)? as usize;
+    }
+    if flags & 0x20 != 0 {
+        position += 2;
+    }
+    let config = descriptor(es.get(position..)?, 0x04)?;
+    let codec = match config.first()? {
+        // MPEG-1 and MPEG-2 audio
+        0x69 | 0x6B => Codec::Mp3,
+        _ => Codec::Aac,
+    };
+    Some((
+        codec,
+        big_endian(config.get(5..9)?),
+        big_endian(config.get(9..13)?),
+    ))
+}
+
+/// The body of the descriptor with the given tag at the start of the data,
+/// its length is told by up to 4 bytes of 7 bits.
+fn descriptor(data: &[u8], tag: u8) -> Option<&[u8]> {
+    if *data.first()? != tag {
+        return None;
+    }
+    let mut length = 0;
+    let mut position = 1;
+    loop {
+        let byte = *data.get(position)?;
+        position += 1;
+        length = (length << 7) | (byte & 0x7F) as usize;
+        if byte & 0x80 == 0 || position == 5 {
+            break;
+        }
+    }
+    data.get(position..(position + length).min(data.len()))
+}
+
+/// Searches the top level moov atom and reads it completely, all the
+/// others read from it.
+pub fn read_moov<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut position = reader.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; 16];
+    loop {
+        reader.seek(SeekFrom::Start(position))?;
+        reader.read_exact(&mut header[0..8])?;
+        let (header_size, size) = match big_endian(&header[0..4]) {
+            1 => {
+                reader.read_exact(&mut header[8..16])?;
+                (16, big_endian(&header[8..16]))
+            }
+            size => (8, size),
+        };
+        if &header[4..8] == b"moov" {
+            if size < header_size || size > MOOV_SIZE_MAX {
+                return Err(invalid_data("moov atom size not supported"));
+            }
+            let mut moov = vec![0u8; (size - header_size) as usize];
+            reader.read_exact(&mut moov)?;
+            return Ok(moov);
+        }
+        position = match position.checked_add(size) {
+            Some(next) if size >= header_size => next,
+            _ => return Err(invalid_data("no moov atom found")),
+        };
+    }
+}
+
+/// Timescale and duration of a mvhd or mdhd atom.
+fn read_time_header(data: &[u8]) -> Option<(u64, u64)> {
+    match data.first()? {
+        0 if data.len() >= 20 => Some((big_endian(&data[12..16]), big_endian(&data[16..20]))),
+        1 if data.len() >= 32 => Some((big_endian(&data[20..24]), big_endian(&data[24..32]))),
+        _ => None,
+    }
+}
+
+/// The Nero chapter list with its start times in 100ns units, chapters
+/// with broken start times are skipped.
+fn read_nero_chapters(data: &[u8]) -> Option<Vec<(String, Duration)>> {
+    let mut position = if *data.first()? == 0 { 4 } else { 8 };
+    let count = *data.get(position)? as usize;
+    position += 1;
+    let mut chapters = vec![];
+    for _ in 0..count {
+        let start = big_endian(data.get(position..position + 8)?);
+        let length = *data.get(position + 8)? as usize;
+        let title = data.get(position + 9..position + 9 + length)?;
+        position += 9 + length;
+        if let Some(nanos) = start.checked_mul(100) {
+            chapters.push((
+                String::from_utf8_lossy(title).to_string(),
+                Duration::from_nanos(nanos),
+            ));
+        }
+    }
+    Some(chapters)
+}
+
+/// The samples of the chapter track referenced by any other track.
+fn read_chapter_track<R: Read + Seek>(
+    reader: &mut R,
+    moov: &[u8],
+) -> io::Result<Vec<(String, Duration)>> {
+    let tracks: Vec<&[u8]> = atoms(moov)
+        .filter(|(name, _)| name == b"trak")
+        .map(|(_, body)| body)
+        .collect();
+    let chapter_ids: Vec<u64> = tracks
+        .iter()
+        .filter_map(|track| child(track, &[b"tref", b"chap"]))
+        .flat_map(|ids| ids.chunks(4).filter(|id| id.len() == 4).map(big_endian))
+        .collect();
+    let chapter_track = tracks.iter().find(|track| {
+        matches!(
+            child(track, &[b"tkhd"]).and_then(read_track_id),
+            Some(id) if chapter_ids.contains(&id)
+        )
+    });
+    let chapter_track = match chapter_track {
+        Some(chapter_track) => chapter_track,
+        None => return Ok(vec![]),
+    };
+
+    let timescale = child(chapter_track, &[b"mdia", b"mdhd"])
+        .and_then(read_time_header)
+        .map(|(timescale, _)| timescale)
+        .unwrap_or(0);
+    let sample_table = child(chapter_track, &[b"mdia", b"minf", b"stbl"])
+        .ok_or_else(|| invalid_data("chapter track without sample table"))?;
+    let offsets =
+        sample_offsets(sample_table).ok_or_else(|| invalid_data("broken sample table"))?;
+    let starts = sample_starts(sample_table, offsets.len())
+        .ok_or_else(|| invalid_data("broken sample times"))?;
+
+    let mut chapters = vec![];
+    for (offset, start) in offsets.iter().zip(starts) {
+        reader.seek(SeekFrom::Start(*offset))?;
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length)?;
+        let mut title = vec![0u8; big_endian(&length) as usize];
+        reader.read_exact(&mut title)?;
+        chapters.push((read_text(&title), scaled(start, timescale)));
+    }
+    Ok(chapters)
+}
+
+fn read_track_id(data: &[u8]) -> Option<u64> {
+    match data.first()? {
+        0 => data.get(12..16).map(big_endian),
+        _ => data.get(20..24).map(big_endian),
+    }
+}
+
+/// The file offsets of all samples, by chunk offsets (stco/co64),
+/// samples per chunk (stsc) and sample sizes (stsz).
+fn sample_offsets(sample_table: &[u8]) -> Option<Vec<u64>> {
+    let (chunk_offsets, width): (&[u8], usize) = match child(sample_table, &[b"stco"]) {
+        Some(stco) => (stco, 4),
+        None => (child(sample_table, &[b"co64"])?, 8),
+    };
+    let chunk_offsets: Vec<u64> = table(chunk_offsets, width)?.map(big_endian).collect();
+
+    let stsz = child(sample_table, &[b"stsz"])?;
+    let fixed_size = big_endian(stsz.get(4..8)?);
+    // the count comes from the file, the sizes are bounded by their atom
+    let sample_count = (big_endian(stsz.get(8..12)?) as usize).min(CHAPTER_SAMPLES_MAX);
+    let sizes: Vec<u64> = if fixed_size == 0 {
+        stsz.get(12..)?
+            .chunks_exact(4)
+            .take(sample_count)
+            .map(big_endian)
+            .collect()
+    } else {
+        vec![fixed_size; sample_count]
+    };
+
+    let samples_per_chunk: Vec<(u64, u64)> = table(child(sample_table, &[b"stsc"])?, 12)?
+        .map(|entry| (big_endian(&entry[0..4]), big_endian(&entry[4..8])))
+        .collect();
+
+    let mut offsets = vec![];
+    let mut sample_sizes = sizes.iter();
+    for (index, chunk_offset) in chunk_offsets.iter().enumerate() {
+        let chunk = index as u64 + 1;
+        let samples = samples_per_chunk
+            .iter()
+            .rev()
+            .find(|(first_chunk, _)| *first_chunk <= chunk)
+            .map_or(0, |(_, samples)| *samples);
+        let mut offset = *chunk_offset;
+        // there are no more samples than sizes
+        for size in sample_sizes.by_ref().take(samples as usize) {
+            offsets.push(offset);
+            offset = offset.saturating_add(*size);
+        }
+    }
+    Some(offsets)
+}
+
+/// The start times of the samples from the time to sample table (stts),
+/// of no more than the given number of samples.
+fn sample_starts(sample_table: &[u8], samples: usize) -> Option<Vec<u64>> {
+    let mut starts = vec![];
+    let mut time: u64 = 0;
+    for entry in table(child(sample_table, &[b"stts"])?, 8)? {
+        let count = (big_endian(&entry[0..4]) as usize).min(samples - starts.len());
+        for _ in 0..count {
+            starts.push(time);
+            time = time.saturating_add(big_endian(&entry[4..8]));
+        }
+    }
+    Some(starts)
+}
+
+/// The entries of a table atom: version/flags, count and the entries.
+fn table(data: &[u8], width: usize) -> Option<impl Iterator<Item = &[u8]>> {
+    let count = big_endian(data.get(4..8)?) as usize;
+    Some(data.get(8..)?.chunks_exact(width).take(count))
+}
+
+/// Chapter titles are UTF-8, or UTF-16 if they start with a byte order mark.
+fn read_text(data: &[u8]) -> String {
+    if data.starts_with(&[0xFE, 0xFF]) {
+        let utf16: Vec<u16> = data[2..]
+            .chunks_exact(2)
+            .map(|pair| (pair[0] as u16) << 8 | pair[1] as u16)
+            .collect();
+        String::from_utf16_lossy(&utf16)
+    } else {
+        String::from_utf8_lossy(data).to_string()
+    }
+}
+
+/// Every chapter ends where the next one starts, the last one with the file.
+fn with_ends(starts: Vec<(String, Duration)>, total: Duration) -> Vec<Chapter> {
+    let ends: Vec<Duration> = starts
+        .iter()
+        .skip(1)
+        .map(|(_, start)| *start)
+        .chain(std::iter::once(total))
+        .collect();
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(|((title, start), end)| Chapter {
+            title,
+            start,
+            end: end.max(start),
+        })
+        .collect()
+}
+
+/// The time in the timescale of the file, broken times must not overflow.
+fn scaled(time: u64, timescale: u64) -> Duration {
+    let millis = (time as u128 * 1000)
+        .checked_div(timescale as u128)
+        .unwrap_or(0);
+    Duration::from_millis(millis.min(u64::MAX as u128) as u64)
+}
+
+fn big_endian(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn atom(name: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend_from_slice(name);
+        atom.extend_from_slice(body);
+        atom
+    }
+
+    fn full_atom(name: &[u8], values: &[u32]) -> Vec<u8> {
+        let body: Vec<u8> = values
+            .iter()
+            .flat_map(|v| v.to_be_bytes().to_vec())
+            .collect();
+        atom(name, &body)
+    }
+
+    fn mvhd(timescale: u32, duration: u32) -> Vec<u8> {
+        full_atom(b"mvhd", &[0, 0, 0, timescale, duration])
+    }
+
+    #[test]
+    fn mp4_nero_chapters() {
+        let mut chpl = vec![1, 0, 0, 0, 0, 0, 0, 0, 3];
+        // the broken start time is skipped
+        let starts = [
+            (0, "Intro"),
+            (u64::MAX, "Broken"),
+            (600_000_000, "Chapter 1"),
+        ];
+        for (start, title) in &starts {
+            chpl.extend_from_slice(&start.to_be_bytes());
+            chpl.push(title.len() as u8);
+            chpl.extend_from_slice(title.as_bytes());
+        }
+        let mut moov = mvhd(1000, 180_000);
+        moov.extend(atom(b"udta", &atom(b"chpl", &chpl)));
+        let mut data = atom(b"ftyp", b"M4B ");
+        data.extend(atom(b"moov", &moov));
+
+        let mut reader = Cursor::new(data);
+        let moov = read_moov(&mut reader).unwrap();
+        let chapters = read_chapters(&mut reader, &moov).unwrap();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].title, "Intro");
+        assert_eq!(chapters[0].end, Duration::from_secs(60));
+        assert_eq!(chapters[1].start, Duration::from_secs(60));
+        assert_eq!(chapters[1].end, Duration::from_secs(180));
+        assert_eq!(read_cover(&moov), None);
+    }
+
+    #[test]
+    fn mp4_broken_atom_size() {
+        let mut data = atom(b"ftyp", b"M4B ");
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(b"free");
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        let error = read_moov(&mut Cursor::new(data)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn mp4_cover() {
+        // type 13 is jpeg, then the locale
+        let data = [&[0, 0, 0, 13, 0, 0, 0, 0][..], b"\xFF\xD8\xFF"].concat();
+        let ilst = atom(b"ilst", &atom(b"covr", &atom(b"data", &data)));
+        let meta = atom(b"meta", &[&[0, 0, 0, 0][..], &ilst].concat());
+        let moov = [mvhd(1000, 1000), atom(b"udta", &meta)].concat();
+        let file = [atom(b"ftyp", b"M4B "), atom(b"moov", &moov)].concat();
+
+        let cover = read_cover(&read_moov(&mut Cursor::new(file)).unwrap());
+        assert_eq!(cover, Some(b"\xFF\xD8\xFF".to_vec()));
+    }
+
+    #[test]
+    fn mp4_quality() {
+        // AAC of 64 to 96kbit/s, the length of the descriptors in 4 bytes
+        let mut decoder_config = vec![0x04, 0x80, 0x80, 0x80, 13, 0x40, 0x15, 0, 0, 0];
+        decoder_config.extend_from_slice(&96_000u32.to_be_bytes());
+        decoder_config.extend_from_slice(&64_000u32.to_be_bytes());
+        let es = [
+            &[0x03, decoder_config.len() as u8 + 3, 0, 1, 0][..],
+            &decoder_config,
+        ]
+        .concat();
+        let esds = atom(b"esds", &[&[0, 0, 0, 0][..], &es].concat());
+        let mut mp4a = vec![0u8; 28];
+        mp4a[17] = 2;
+        mp4a[24..26].copy_from_slice(&44100u16.to_be_bytes());
+        mp4a.extend(esds);
+        let stsd = atom(
+            b"stsd",
+            &[&[0, 0, 0, 0, 0, 0, 0, 1][..], &atom(b"mp4a", &mp4a)].concat(),
+        );
+        let hdlr = full_atom(b"hdlr", &[0, 0, u32::from_be_bytes(*b"soun")]);
+        let mdia = atom(
+            b"mdia",
+            &[hdlr, atom(b"minf", &atom(b"stbl", &stsd))].concat(),
+        );
+        let moov = [mvhd(1000, 1000), atom(b"trak", &mdia)].concat();
+        let file = [atom(b"ftyp", b"M4B "), atom(b"moov", &moov)].concat();
+
+        let quality = read_quality(&read_moov(&mut Cursor::new(file)).unwrap());
+        assert_eq!(
+            quality,
+            Some(Quality::new(Codec::Aac, 64, Some(true), 44100, 2))
+        );
+    }
+
+    #[test]
+    fn mp4_chapter_track() {
+        // two text samples in one chunk, the titles are in the mdat atom
+        let mut mdat = vec![];
+        for title in &["Eins", "Zwei"] {
+            mdat.extend_from_slice(&(title.len() as u16).to_be_bytes());
+            mdat.extend_from_slice(title.as_bytes());
+        }
+        let ftyp = atom(b"ftyp", b"M4A ");
+        let mdat_offset = (ftyp.len() + 8) as u32;
+
+        let audio_track = [
+            full_atom(b"tkhd", &[0, 0, 0, 1]),
+            atom(b"tref", &full_atom(b"chap", &[2])),
+        ]
+        .concat();
+        let stbl = [
+            full_atom(b"stts", &[0, 2, 1, 30, 1, 60]),
+            full_atom(b"stsz", &[0, 0, 2, 6, 6]),
+            full_atom(b"stsc", &[0, 1, 1, 2, 1]),
+            full_atom(b"stco", &[0, 1, mdat_offset]),
+        ]
+        .concat();
+        let chapter_track = [
+            full_atom(b"tkhd", &[0, 0, 0, 2]),
+            atom(
+                b"mdia",
+                &[
+                    full_atom(b"mdhd", &[0, 0, 0, 1, 90]),
+                    atom(b"minf", &atom(b"stbl", &stbl)),
+                ]
+                .concat(),
+            ),
+        ]
+        .concat();
+        let moov = [
+            mvhd(1, 90),
+            atom(b"trak", &audio_track),
+            atom(b"trak", &chapter_track),
+        ]
+        .concat();
+        let data = [ftyp, atom(b"mdat", &mdat), atom(b"moov", &moov)].concat();
+
+        let mut reader = Cursor::new(data);
+        let moov = read_moov(&mut reader).unwrap();
+        let chapters = read_chapters(&mut reader, &moov).unwrap();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[1].title, "Zwei");
+        assert_eq!(chapters[1].start, Duration::from_secs(30));
+        assert_eq!(chapters[1].end, Duration::from_secs(90));
+    }
+
+    #[test]
+    fn mp4_broken_counts() {
+        // a fixed sample size with 4 billion samples in a single chunk
+        let stbl = [
+            full_atom(b"stts", &[0, 1, u32::MAX, 1]),
+            full_atom(b"stsz", &[0, 1, u32::MAX]),
+            full_atom(b"stsc", &[0, 1, 1, u32::MAX, 1]),
+            full_atom(b"stco", &[0, 1, 0]),
+        ]
+        .concat();
+        let offsets = sample_offsets(&stbl).unwrap();
+        assert_eq!(offsets.len(), CHAPTER_SAMPLES_MAX);
+        assert_eq!(
+            sample_starts(&stbl, offsets.len()).unwrap().len(),
+            offsets.len()
+        );
+        assert_eq!(scaled(u64::MAX, 1), Duration::from_millis(u64::MAX));
+        assert_eq!(scaled(1000, 0), Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,370 @@
+//! Reads the MPEG audio stream (mp3 and its older layers) to find out the real
+//! duration and the quality of a file. Tags rarely carry the duration (TLEN),
+//! so it is taken from the Xing/Info or VBRI header of the first frame, or if
+//! there is none, by scanning all frames.
+use super::quality::{self, Codec, Quality};
+use std::{
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    time::Duration,
+};
+
+/// Bitrates in kbit/s for MPEG1 layer I, II, III and MPEG2/2.5 layer I, II/III
+static BITRATES: [[u32; 16]; 5] = [
+    [
+        0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448, 0,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384, 0,
+    ],
+    [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 0,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256, 0,
+    ],
+    [
+        0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160, 0,
+    ],
+];
+/// Sample rates for MPEG1, MPEG2, MPEG2.5
+static SAMPLE_RATES: [[u32; 3]; 3] = [
+    [44100, 48000, 32000],
+    [22050, 24000, 16000],
+    [11025, 12000, 8000],
+];
+
+/// How many bytes are tried to find the first frame after the tags.
+static SYNC_SEARCH_LIMIT: u64 = 64 * 1024;
+
+/// Duration and quality of an mpeg audio stream.
+pub struct MpegStream {
+    pub duration: Duration,
+    pub quality: Quality,
+}
+
+/// What the Xing/Info or VBRI header of the first frame tells.
+struct VbrHeader {
+    frames: u64,
+    /// bytes of all frames, if told
+    bytes: Option<u64>,
+    /// LAME writes an Info header into files of constant bitrate
+    variable: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Version {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25,
+}
+
+/// A single decoded 4 byte frame header.
+#[derive(Clone, Copy, Debug)]
+struct FrameHeader {
+    version: Version,
+    layer: u8,
+    bitrate: u32,
+    sample_rate: u32,
+    padding: bool,
+    mono: bool,
+}
+
+impl FrameHeader {
+    fn parse(bytes: [u8; 4]) -> Option<Self> {
+        if bytes[0] != 0xFF || bytes[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+        let version = match (bytes[1] >> 3) & 0x03 {
+            0 => Version::Mpeg25,
+            2 => Version::Mpeg2,
+            3 => Version::Mpeg1,
+            _ => return None,
+        };
+        let layer = match (bytes[1] >> 1) & 0x03 {
+            1 => 3,
+            2 => 2,
+            3 => 1,
+            _ => return None,
+        };
+        let bitrate_index = (bytes[2] >> 4) as usize;
+        let sample_rate_index = ((bytes[2] >> 2) & 0x03) as usize;
+        if sample_rate_index == 3 {
+            return None;
+        }
+        let bitrate_row = match (version, layer) {
+            (Version::Mpeg1, layer) => (layer - 1) as usize,
+            (_, 1) => 3,
+            (_, _) => 4,
+        };
+        let bitrate = BITRATES[bitrate_row][bitrate_index];
+        // free format is not supported (and very unusual)
+        if bitrate == 0 {
+            return None;
+        }
+        let sample_rate = SAMPLE_RATES[match version {
+            Version::Mpeg1 => 0,
+            Version::Mpeg2 => 1,
+            Version::Mpeg25 => 2,
+        }][sample_rate_index];
+        Some(Self {
+            version,
+            layer,
+            bitrate: bitrate * 1000,
+            sample_rate,
+            padding: (bytes[2] >> 1) & 0x01 == 1,
+            mono: (bytes[3] >> 6) == 0x03,
+        })
+    }
+
+    fn samples_per_frame(&self) -> u32 {
+        match (self.layer, self.version) {
+            (1, _) => 384,
+            (2, _) => 1152,
+            (_, Version::Mpeg1) => 1152,
+            (_, _) => 576,
+        }
+    }
+
+    fn frame_length(&self) -> u32 {
+        let padding = if self.padding { 1 } else { 0 };
+        if self.layer == 1 {
+            (12 * self.bitrate / self.sample_rate + padding) * 4
+        } else {
+            self.samples_per_frame() / 8 * self.bitrate / self.sample_rate + padding
+        }
+    }
+
+    /// Where the Xing/Info header would start inside the frame
+    fn xing_offset(&self) -> usize {
+        4 + match (self.version, self.mono) {
+            (Version::Mpeg1, false) => 32,
+            (Version::Mpeg1, true) => 17,
+            (_, false) => 17,
+            (_, true) => 9,
+        }
+    }
+
+    fn duration_of(&self, frames: u64) -> Duration {
+        let samples = frames * self.samples_per_frame() as u64;
+        Duration::from_millis(samples * 1000 / self.sample_rate as u64)
+    }
+
+    /// The stream of that many frames, with their average bitrate if it varies.
+    fn stream(&self, frames: u64, bytes: Option<u64>, variable: bool) -> MpegStream {
+        let duration = self.duration_of(frames);
+        let bitrate = match (variable, bytes) {
+            (false, _) => self.bitrate / 1000,
+            (true, Some(bytes)) => quality::bitrate_of(bytes, duration),
+            // estimated by the file size later
+            (true, None) => 0,
+        };
+        let codec = match self.layer {
+            1 => Codec::Mp1,
+            2 => Codec::Mp2,
+            _ => Codec::Mp3,
+        };
+        let channels = if self.mono { 1 } else { 2 };
+        MpegStream {
+            duration,
+            quality: Quality::new(codec, bitrate, Some(variable), self.sample_rate, channels),
+        }
+    }
+}
+
+/// Returns the duration and the quality of the mpeg audio stream.
+pub fn read_stream<R: Read + Seek>(reader: &mut R) -> io::Result<MpegStream> {
+    let mut reader = BufReader::new(reader);
+    let start = skip_id3v2(&mut reader)?;
+    let (first_frame, header) = find_first_frame(&mut reader, start)?;
+
+    // first frame may be a Xing/Info or VBRI frame and knows it all
+    let mut frame = vec![0u8; header.frame_length() as usize];
+    reader.seek(SeekFrom::Start(first_frame))?;
+    let read = read_up_to(&mut reader, &mut frame)?;
+    if let Some(vbr) = vbr_header(&header, &frame[..read]) {
+        return Ok(header.stream(vbr.frames, vbr.bytes, vbr.variable));
+    }
+
+    // no header, so count all frames
+    reader.seek(SeekFrom::Start(first_frame))?;
+    let mut frames: u64 = 0;
+    let mut length: u64 = 0;
+    let mut variable = false;
+    let mut bytes = [0u8; 4];
+    loop {
+        if read_up_to(&mut reader, &mut bytes)? < 4 {
+            break;
+        }
+        match FrameHeader::parse(bytes) {
+            Some(next) => {
+                frames += 1;
+                length += next.frame_length() as u64;
+                variable |= next.bitrate != header.bitrate;
+                reader.seek_relative(next.frame_length() as i64 - 4)?;
+            }
+            // end of stream (or ID3v1/APE tag at the end)
+            None => break,
+        }
+    }
+    Ok(header.stream(frames, Some(length), variable))
+}
+
+/// Returns the position after a leading ID3v2 tag (or 0).
+pub fn skip_id3v2<R: Read + Seek>(reader: &mut R) -> io::Result<u64> {
+    let mut header = [0u8; 10];
+    reader.seek(SeekFrom::Start(0))?;
+    if read_up_to(reader, &mut header)? == 10 && &header[0..3] == b"ID3" {
+        let size = synchsafe(&header[6..10]) as u64;
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        Ok(10 + size + footer)
+    } else {
+        Ok(0)
+    }
+}
+
+/// Searches the first frame which is followed by another valid frame.
+fn find_first_frame<R: Read + Seek>(reader: &mut R, start: u64) -> io::Result<(u64, FrameHeader)> {
+    let mut position = start;
+    let mut bytes = [0u8; 4];
+    while position < start + SYNC_SEARCH_LIMIT {
+        reader.seek(SeekFrom::Start(position))?;
+        if read_up_to(reader, &mut bytes)? < 4 {
+            break;
+        }
+        if let Some(header) = FrameHeader::parse(bytes) {
+            // check next header to not be fooled by some random bytes
+            let next = position + header.frame_length() as u64;
+            reader.seek(SeekFrom::Start(next))?;
+            let read = read_up_to(reader, &mut bytes)?;
+            if read < 4 || FrameHeader::parse(bytes).is_some() {
+                return Ok((position, header));
+            }
+        }
+        position += 1;
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "no mpeg audio frame found",
+    ))
+}
+
+/// The Xing/Info or VBRI header inside the first frame, if it counts the frames.
+fn vbr_header(header: &FrameHeader, frame: &[u8]) -> Option<VbrHeader> {
+    let xing = header.xing_offset();
+    if frame.len() >= xing + 12
+        && (&frame[xing..xing + 4] == b"Xing" || &frame[xing..xing + 4] == b"Info")
+    {
+        let flags = big_endian(&frame[xing + 4..xing + 8]);
+        // frames field is present, the bytes field follows
+        if flags & 0x01 != 0 {
+            return Some(VbrHeader {
+                frames: big_endian(&frame[xing + 8..xing + 12]) as u64,
+                bytes: frame
+                    .get(xing + 12..xing + 16)
+                    .filter(|_| flags & 0x02 != 0)
+                    .map(|bytes| big_endian(bytes) as u64),
+                variable: &frame[xing..xing + 4] == b"Xing",
+            });
+        }
+    }
+    // VBRI is always 32 bytes after the header
+    let vbri = 4 + 32;
+    if frame.len() >= vbri + 18 && &frame[vbri..vbri + 4] == b"VBRI" {
+        return Some(VbrHeader {
+            frames: big_endian(&frame[vbri + 14..vbri + 18]) as u64,
+            bytes: Some(big_endian(&frame[vbri + 10..vbri + 14]) as u64),
+            variable: true,
+        });
+    }
+    None
+}
+
+/// Reads until the buffer is full or the end is reached.
+pub fn read_up_to<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+fn big_endian(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 8) | *byte as u32)
+}
+
+/// Sizes inside ID3v2 tags use only 7 bits of each byte.
+pub fn synchsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 7) | (*byte & 0x7F) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // MPEG1 layer III, 128kbit/s, 44.1kHz, no padding, stereo: 417 bytes per frame
+    static HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+
+    fn frames(nr: usize) -> Vec<u8> {
+        let mut data = vec![];
+        for _ in 0..nr {
+            data.extend_from_slice(&HEADER);
+            data.extend_from_slice(&[0u8; 413]);
+        }
+        data
+    }
+
+    #[test]
+    fn mpeg_frame_scan() {
+        let mut data = b"ID3\x03\x00\x00\x00\x00\x00\x0A".to_vec();
+        data.extend_from_slice(&[0u8; 10]);
+        data.extend(frames(100));
+        let stream = read_stream(&mut Cursor::new(data)).unwrap();
+        // 100 * 1152 / 44100
+        assert_eq!(stream.duration, Duration::from_millis(2612));
+        assert_eq!(
+            stream.quality,
+            Quality::new(Codec::Mp3, 128, Some(false), 44100, 2)
+        );
+    }
+
+    #[test]
+    fn mpeg_xing_header() {
+        let mut data = frames(3);
+        data[36..40].copy_from_slice(b"Xing");
+        data[40..44].copy_from_slice(&[0, 0, 0, 1]);
+        data[44..48].copy_from_slice(&[0, 0, 0x03, 0xE8]);
+        let stream = read_stream(&mut Cursor::new(data.clone())).unwrap();
+        // 1000 * 1152 / 44100
+        assert_eq!(stream.duration, Duration::from_millis(26122));
+        assert_eq!(stream.quality.variable, Some(true));
+        // without a bytes field the bitrate is left to the file size
+        assert_eq!(stream.quality.bitrate, 0);
+
+        // 2612250 bytes in 26.122s
+        data[40..44].copy_from_slice(&[0, 0, 0, 3]);
+        data[48..52].copy_from_slice(&2_612_250u32.to_be_bytes());
+        let stream = read_stream(&mut Cursor::new(data.clone())).unwrap();
+        assert_eq!(stream.quality.bitrate, 800);
+
+        // an Info header is written for constant bitrates
+        data[36..40].copy_from_slice(b"Info");
+        let stream = read_stream(&mut Cursor::new(data)).unwrap();
+        assert_eq!(stream.duration, Duration::from_millis(26122));
+        assert_eq!(stream.quality.variable, Some(false));
+        assert_eq!(stream.quality.bitrate, 128);
+    }
+
+    #[test]
+    fn mpeg_no_frames() {
+        let data = vec![0u8; 2000];
+        assert!(read_stream(&mut Cursor::new(data)).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Normalizes artist and title before they become the key of a book, so that
+//! spelling noise (case, accents, track numbers, punctuation) does not count
+//! into the string distance. Part numbers tell books apart, they are only
+//! dropped on request. The original strings stay in the audio info.
+use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
+
+lazy_static! {
+    /// "01 - ", "1. ", "Track 03: " at the beginning
+    static ref LEADING_NUMBER: Regex =
+        Regex::new(r"(?i)^\s*(?:track\s*)?\d{1,3}\s*[-._:)\]]+\s*").unwrap();
+    /// "Part 2", "Kapitel 12", "CD III" anywhere
+    static ref PART_NUMBER: Regex = Regex::new(
+        r"(?i)\b(?:part|chapter|teil|kapitel|cd|disc|disk|track)\.?\s*(?:\d+|[ivx]+)\b"
+    )
+    .unwrap();
+}
+
+/// The steps to normalize a key with, in the order they are applied.
+#[derive(Clone, Debug)]
+pub struct KeyNormalization {
+    /// unicode NFKC, so that e.g. ligatures and full width letters are the same
+    pub unicode: bool,
+    /// transliterate to ASCII, accents are dropped ("Müller" -> "Muller")
+    pub transliterate: bool,
+    /// lower case, "ß" becomes "ss"
+    pub case_fold: bool,
+    /// drop leading track numbers
+    pub strip_numbering: bool,
+    /// drop "Part/Chapter N", then the parts of a book are duplicates
+    pub strip_parts: bool,
+    /// punctuation becomes a space
+    pub strip_punctuation: bool,
+    /// trim and only single spaces
+    pub collapse_whitespace: bool,
+}
+
+impl Default for KeyNormalization {
+    fn default() -> Self {
+        Self {
+            unicode: true,
+            transliterate: true,
+            case_fold: true,
+            strip_numbering: true,
+            strip_parts: false,
+            strip_punctuation: true,
+            collapse_whitespace: true,
+        }
+    }
+}
+
+impl KeyNormalization {
+    /// Keeps the strings as they are.
+    pub fn none() -> Self {
+        Self {
+            unicode: false,
+            transliterate: false,
+            case_fold: false,
+            strip_numbering: false,
+            strip_parts: false,
+            strip_punctuation: false,
+            collapse_whitespace: false,
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut normalized = text.to_string();
+        if self.unicode {
+            normalized = normalized.nfkc().collect();
+        }
+        if self.transliterate {
+            normalized = deunicode::deunicode(&normalized);
+        }
+        if self.case_fold {
+            normalized = normalized.to_lowercase().replace('ß', "ss");
+        }
+        if self.strip_numbering {
+            normalized = LEADING_NUMBER.replace(&normalized, "").to_string();
+        }
+        if self.strip_parts {
+            normalized = PART_NUMBER.replace_all(&normalized, " ").to_string();
+        }
+        if self.strip_punctuation {
+            normalized = normalized
+                .chars()
+                .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+                .collect();
+        }
+        if self.collapse_whitespace {
+            normalized = normalized.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        normalized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_noise_away() {
+        let normalization = KeyNormalization::default();
+        assert_eq!(
+            normalization.apply("01 - Der  Zauberberg, Kapitel 1"),
+            "der zauberberg kapitel 1"
+        );
+        assert_eq!(
+            normalization.apply("Thomas MANN"),
+            normalization.apply("Thomas Mann")
+        );
+        assert_eq!(
+            normalization.apply("Süßkind: Das Parfum"),
+            "susskind das parfum"
+        );
+        assert_eq!(normalization.apply("ＡＢＣ ﬁsh"), "abc fish");
+        // a number that is the title stays
+        assert_eq!(normalization.apply("1984"), "1984");
+    }
+
+    #[test]
+    fn normalize_parts_on_request() {
+        let normalization = KeyNormalization::default();
+        assert_ne!(
+            normalization.apply("Harry Potter, Teil 1"),
+            normalization.apply("Harry Potter, Teil 2")
+        );
+        let normalization = KeyNormalization {
+            strip_parts: true,
+            ..KeyNormalization::default()
+        };
+        assert_eq!(
+            normalization.apply("01 - Der  Zauberberg, Kapitel 1"),
+            "der zauberberg"
+        );
+    }
+
+    #[test]
+    fn normalize_nothing() {
+        let text = "01 - Der  Zauberberg, Kapitel 1";
+        assert_eq!(KeyNormalization::none().apply(text), text);
+    }
+}
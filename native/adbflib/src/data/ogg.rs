@@ -0,0 +1,334 @@
+//! Reads Ogg Vorbis and Ogg Opus files: the comment header of the first
+//! logical stream holds the tags, the granule position of its last page
+//! gives the duration and the identification header the quality.
+use super::quality::{self, Quality};
+use std::{
+    collections::HashMap,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    mem,
+    time::Duration,
+};
+
+/// An Ogg page is never bigger than this: header, 255 lacing values and 255 * 255 bytes of data.
+static MAX_PAGE_SIZE: u64 = 27 + 255 + 255 * 255;
+/// Opus granule positions are always counted in 48kHz.
+static OPUS_RATE: u64 = 48000;
+
+/// The tags (with upper case keys), the duration and the quality of an Ogg stream.
+pub struct OggStream {
+    pub comments: HashMap<String, Vec<String>>,
+    pub duration: Duration,
+    pub quality: Quality,
+}
+
+impl OggStream {
+    /// The first value of the given (upper case) comment key.
+    pub fn first(&self, key: &str) -> Option<String> {
+        self.comments
+            .get(key)
+            .and_then(|values| values.first())
+            .cloned()
+    }
+}
+
+struct PageHeader {
+    serial: u32,
+    segments: Vec<u8>,
+}
+
+enum Codec {
+    Vorbis { sample_rate: u64 },
+    Opus { pre_skip: u64 },
+}
+
+/// Reads the tags and the duration of an Ogg Vorbis or Opus stream.
+pub fn read_stream<R: Read + Seek>(reader: &mut R) -> io::Result<OggStream> {
+    let mut reader = BufReader::new(reader);
+    reader.seek(SeekFrom::Start(0))?;
+    let (serial, packets) = read_header_packets(&mut reader, 2)?;
+    let (codec, quality) = read_identification(&packets[0])?;
+    let comments = match codec {
+        Codec::Vorbis { .. } => strip_magic(&packets[1], b"\x03vorbis"),
+        Codec::Opus { .. } => strip_magic(&packets[1], b"OpusTags"),
+    }
+    .ok_or_else(|| invalid_data("comment header missing"))
+    .and_then(read_comments)?;
+
+    let granule = last_granule(&mut reader, serial)?.unwrap_or(0);
+    let duration = match codec {
+        Codec::Vorbis { sample_rate } => samples_duration(granule, sample_rate),
+        Codec::Opus { pre_skip } => samples_duration(granule.saturating_sub(pre_skip), OPUS_RATE),
+    };
+    Ok(OggStream {
+        comments,
+        duration,
+        quality,
+    })
+}
+
+/// The duration of that many samples, a broken granule must not overflow.
+fn samples_duration(samples: u64, sample_rate: u64) -> Duration {
+    let millis = (samples as u128 * 1000)
+        .checked_div(sample_rate as u128)
+        .unwrap_or(0);
+    Duration::from_millis(millis.min(u64::MAX as u128) as u64)
+}
+
+/// Reads the next page header, `None` at the end of the file.
+fn read_page_header<R: Read>(reader: &mut R) -> io::Result<Option<PageHeader>> {
+    let mut header = [0u8; 27];
+    match reader.read_exact(&mut header) {
+        Ok(()) => (),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    if &header[0..4] != b"OggS" {
+        return Err(invalid_data("lost ogg page sync"));
+    }
+    let mut segments = vec![0u8; header[26] as usize];
+    reader.read_exact(&mut segments)?;
+    Ok(Some(PageHeader {
+        serial: little_endian(&header[14..18]) as u32,
+        segments,
+    }))
+}
+
+/// Collects the first packets of the first logical stream,
+/// other (multiplexed) streams are skipped.
+fn read_header_packets<R: Read>(reader: &mut R, count: usize) -> io::Result<(u32, Vec<Vec<u8>>)> {
+    let mut serial = None;
+    let mut packets = vec![];
+    let mut packet = vec![];
+    while packets.len() < count {
+        let page =
+            read_page_header(reader)?.ok_or_else(|| invalid_data("ogg headers incomplete"))?;
+        let mut body = vec![0u8; page.segments.iter().map(|lacing| *lacing as usize).sum()];
+        reader.read_exact(&mut body)?;
+        if *serial.get_or_insert(page.serial) != page.serial {
+            continue;
+        }
+        let mut offset = 0;
+        for lacing in page.segments.iter().map(|lacing| *lacing as usize) {
+            packet.extend_from_slice(&body[offset..offset + lacing]);
+            offset += lacing;
+            // a lacing value below 255 ends the packet
+            if lacing < 255 {
+                packets.push(mem::take(&mut packet));
+            }
+        }
+    }
+    packets.truncate(count);
+    Ok((serial.unwrap_or(0), packets))
+}
+
+fn read_identification(packet: &[u8]) -> io::Result<(Codec, Quality)> {
+    if let Some(header) = strip_magic(packet, b"\x01vorbis") {
+        if header.len() >= 9 {
+            // version (4), channels (1), the sample rate, then the
+            // maximum, nominal and minimum bitrate (0 if not set)
+            let sample_rate = little_endian(&header[5..9]);
+            let bitrates: Vec<u64> = (9..21)
+                .step_by(4)
+                .map(|start| header.get(start..start + 4).map_or(0, little_endian))
+                .collect();
+            let variable = bitrates[0] != bitrates[2] || bitrates[0] == 0;
+            let quality = Quality::new(
+                quality::Codec::Vorbis,
+                (bitrates[1] / 1000) as u32,
+                Some(variable).filter(|_| bitrates[1] > 0),
+                sample_rate as u32,
+                header[4],
+            );
+            return Ok((Codec::Vorbis { sample_rate }, quality));
+        }
+    }
+    if let Some(header) = strip_magic(packet, b"OpusHead") {
+        if header.len() >= 4 {
+            // version (1), channels (1), then the pre-skip, the output
+            // is always decoded in 48kHz
+            let quality = Quality::new(quality::Codec::Opus, 0, None, OPUS_RATE as u32, header[1]);
+            return Ok((
+                Codec::Opus {
+                    pre_skip: little_endian(&header[2..4]),
+                },
+                quality,
+            ));
+        }
+    }
+    Err(invalid_data("neither vorbis nor opus"))
+}
+
+/// Parses the vendor string and the "KEY=value" list of a comment header.
+fn read_comments(mut data: &[u8]) -> io::Result<HashMap<String, Vec<String>>> {
+    let _vendor = next_string(&mut data)?;
+    let mut count = [0u8; 4];
+    data.read_exact(&mut count)?;
+
+    let mut comments = HashMap::<String, Vec<String>>::new();
+    for _ in 0..little_endian(&count) {
+        let comment = next_string(&mut data)?;
+        let mut key_value = comment.splitn(2, '=');
+        if let (Some(key), Some(value)) = (key_value.next(), key_value.next()) {
+            comments
+                .entry(key.to_uppercase())
+                .or_default()
+                .push(value.to_string());
+        }
+    }
+    Ok(comments)
+}
+
+/// A string with its 32 bit length in front.
+fn next_string(data: &mut &[u8]) -> io::Result<String> {
+    let mut length = [0u8; 4];
+    data.read_exact(&mut length)?;
+    let length = little_endian(&length) as usize;
+    if length > data.len() {
+        return Err(invalid_data("comment exceeds header"));
+    }
+    let (string, rest) = data.split_at(length);
+    *data = rest;
+    Ok(String::from_utf8_lossy(string).to_string())
+}
+
+/// The granule position of the last page of the stream, searched in the
+/// last bytes of the file, as the last page can't be further away.
+fn last_granule<R: Read + Seek>(reader: &mut R, serial: u32) -> io::Result<Option<u64>> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    let start = end.saturating_sub(MAX_PAGE_SIZE);
+    reader.seek(SeekFrom::Start(start))?;
+    let mut tail = vec![];
+    reader.read_to_end(&mut tail)?;
+
+    let mut position = tail.len().saturating_sub(27);
+    loop {
+        let header = &tail[position..];
+        if header.len() >= 27 && &header[0..4] == b"OggS" {
+            let granule = little_endian(&header[6..14]);
+            // -1 marks pages without any finished packet
+            if little_endian(&header[14..18]) as u32 == serial && granule != u64::MAX {
+                return Ok(Some(granule));
+            }
+        }
+        if position == 0 {
+            return Ok(None);
+        }
+        position -= 1;
+    }
+}
+
+fn strip_magic<'a>(packet: &'a [u8], magic: &[u8]) -> Option<&'a [u8]> {
+    if packet.starts_with(magic) {
+        Some(&packet[magic.len()..])
+    } else {
+        None
+    }
+}
+
+fn little_endian(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn page(serial: u32, granule: u64, packets: &[&[u8]]) -> Vec<u8> {
+        let mut segments = vec![];
+        let mut body = vec![];
+        for packet in packets {
+            segments.extend(vec![255u8; packet.len() / 255]);
+            segments.push((packet.len() % 255) as u8);
+            body.extend_from_slice(packet);
+        }
+        let mut page = b"OggS\x00\x00".to_vec();
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&[0u8; 8]);
+        page.push(segments.len() as u8);
+        page.extend(segments);
+        page.extend(body);
+        page
+    }
+
+    fn comments(magic: &[u8], comments: &[&str]) -> Vec<u8> {
+        let mut packet = magic.to_vec();
+        packet.extend_from_slice(&4u32.to_le_bytes());
+        packet.extend_from_slice(b"test");
+        packet.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            packet.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            packet.extend_from_slice(comment.as_bytes());
+        }
+        packet
+    }
+
+    #[test]
+    fn ogg_vorbis() {
+        let mut identification = b"\x01vorbis\x00\x00\x00\x00\x02".to_vec();
+        identification.extend_from_slice(&44100u32.to_le_bytes());
+        // no maximum and minimum, so the nominal bitrate is an average
+        for bitrate in &[0u32, 96000, 0] {
+            identification.extend_from_slice(&bitrate.to_le_bytes());
+        }
+        let long_comment = format!("DESCRIPTION={}", "x".repeat(300));
+        let mut data = page(7, 0, &[&identification]);
+        data.extend(page(
+            7,
+            0,
+            &[&comments(
+                b"\x03vorbis",
+                &["title=Animal Farm", "ARTIST=George Orwell", &long_comment],
+            )],
+        ));
+        data.extend(page(7, 44100 * 90, &[&[0u8; 100]]));
+
+        let stream = read_stream(&mut Cursor::new(data)).unwrap();
+        assert_eq!(stream.first("TITLE"), Some("Animal Farm".to_string()));
+        assert_eq!(stream.first("ARTIST"), Some("George Orwell".to_string()));
+        assert_eq!(stream.first("DESCRIPTION").map(|d| d.len()), Some(300));
+        assert_eq!(stream.duration, Duration::from_secs(90));
+        assert_eq!(stream.quality.sample_rate, 44100);
+        assert_eq!(stream.quality.channels, 2);
+        assert_eq!(stream.quality.bitrate, 96);
+        assert_eq!(stream.quality.variable, Some(true));
+    }
+
+    #[test]
+    fn ogg_opus() {
+        let mut identification = b"OpusHead\x01\x02".to_vec();
+        identification.extend_from_slice(&4800u16.to_le_bytes());
+        identification.extend_from_slice(&48000u32.to_le_bytes());
+        let mut data = page(1, 0, &[&identification]);
+        data.extend(page(1, 0, &[&comments(b"OpusTags", &["ALBUM=1984"])]));
+        data.extend(page(1, 48000 * 10 + 4800, &[&[0u8; 10]]));
+        // another stream at the end is not ours
+        data.extend(page(2, 48000 * 20, &[&[0u8; 10]]));
+
+        let stream = read_stream(&mut Cursor::new(data)).unwrap();
+        assert_eq!(stream.first("ALBUM"), Some("1984".to_string()));
+        assert_eq!(stream.duration, Duration::from_secs(10));
+        assert_eq!(
+            stream.quality,
+            Quality::new(quality::Codec::Opus, 0, None, 48000, 2)
+        );
+    }
+
+    #[test]
+    fn samples_duration_does_not_overflow() {
+        // broken granules and sample rates
+        assert_eq!(
+            samples_duration(u64::MAX, 1),
+            Duration::from_millis(u64::MAX)
+        );
+        assert_eq!(samples_duration(44100, 0), Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,79 @@
+//! The options of a single collection run, chosen by the user
+//! (unlike the fixed configuration in `common::config`).
+use super::{
+    classify::MimeMapping, filter::ScanRules, metric::Similarity, normalize::KeyNormalization,
+};
+use std::collections::HashMap;
+
+/// Switches that change how files are searched and analyzed.
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+    /// hash the audio payload (without tags) to find identical audio
+    pub hash_audio: bool,
+    /// do not follow symbolic links (they are followed by default)
+    pub skip_symlinks: bool,
+    /// look into ZIP archives and analyze the audio files inside
+    pub scan_archives: bool,
+    /// classify files by their extension, sniff the content only for unknown ones
+    pub fast_classify: bool,
+    /// leave out books told to be music or podcasts
+    pub audiobooks_only: bool,
+    /// extensions to mime types and mime types to tag readers
+    pub mime_mapping: MimeMapping,
+    /// include and exclude globs for all search paths
+    pub rules: ScanRules,
+    /// include and exclude globs for single search paths, in addition
+    pub path_rules: HashMap<String, ScanRules>,
+    /// how artist and title are normalized before they are compared
+    pub key_normalization: KeyNormalization,
+    /// how keys are compared and up to where they are similar
+    pub similarity: Similarity,
+}
+
+impl ScanOptions {
+    /// Takes `path=glob`, like `/media/books=*.m4b`, to search only
+    /// matching files in that search path.
+    pub fn include_in(&mut self, assignment: &str) -> Result<(), String> {
+        let (path, glob) = split_path_rule(assignment)?;
+        self.path_rules.entry(path).or_default().include.push(glob);
+        Ok(())
+    }
+
+    /// Takes `path=glob`, like `/media/books=podcasts/*`, to leave out
+    /// matching files and folders in that search path.
+    pub fn exclude_in(&mut self, assignment: &str) -> Result<(), String> {
+        let (path, glob) = split_path_rule(assignment)?;
+        self.path_rules.entry(path).or_default().exclude.push(glob);
+        Ok(())
+    }
+}
+
+/// Paths may contain `=` more likely than globs, so the last one splits.
+fn split_path_rule(assignment: &str) -> Result<(String, String), String> {
+    match assignment.rsplit_once('=') {
+        Some((path, glob)) if !path.trim().is_empty() && !glob.trim().is_empty() => {
+            Ok((path.trim().to_string(), glob.trim().to_string()))
+        }
+        _ => Err(format!("{} is not of the form path=glob", assignment)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_of_single_paths() {
+        let mut options = ScanOptions::default();
+        options.exclude_in("/media/a=b=podcasts/*").unwrap();
+        options.exclude_in("/media/a=b=@eaDir").unwrap();
+        options.include_in("/books=*.m4b").unwrap();
+        assert!(options.include_in("/books=").is_err());
+        assert!(options.exclude_in("*.mp3").is_err());
+
+        let rules = &options.path_rules["/media/a=b"];
+        assert_eq!(rules.exclude, vec!["podcasts/*", "@eaDir"]);
+        assert!(rules.include.is_empty());
+        assert_eq!(options.path_rules["/books"].include, vec!["*.m4b"]);
+    }
+}
@@ -0,0 +1,198 @@
+//! Hashes only the audio payload of a file, without any tags, so retagged
+//! copies of the same audio still have the same hash.
+//! MP3: without ID3v2, ID3v1 and APE tags, FLAC: without the metadata
+//! blocks, MP4: only the `mdat` atoms.
+use super::mpeg::{read_up_to, skip_id3v2};
+use blake3;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// The size of the chunks fed to the hasher.
+static CHUNK_SIZE: usize = 64 * 1024;
+
+/// Returns the hash of the audio payload as hex string,
+/// or `None` if the format is not known.
+pub fn hash_audio_payload<R: Read + Seek>(reader: &mut R) -> io::Result<Option<String>> {
+    let ranges = match payload_ranges(reader)? {
+        Some(ranges) => ranges,
+        None => return Ok(None),
+    };
+    let mut hasher = blake3::Hasher::new();
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    for (start, end) in ranges {
+        reader.seek(SeekFrom::Start(start))?;
+        let mut left = end.saturating_sub(start);
+        while left > 0 {
+            let size = (left as usize).min(CHUNK_SIZE);
+            reader.read_exact(&mut chunk[..size])?;
+            hasher.update(&chunk[..size]);
+            left -= size as u64;
+        }
+    }
+    Ok(Some(hasher.finalize().to_hex().to_string()))
+}
+
+/// The byte ranges (start, end) of the audio payload.
+fn payload_ranges<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Vec<(u64, u64)>>> {
+    let file_size = reader.seek(SeekFrom::End(0))?;
+    let start = skip_id3v2(reader)?;
+    let mut magic = [0u8; 8];
+    reader.seek(SeekFrom::Start(start))?;
+    if read_up_to(reader, &mut magic)? < 8 {
+        return Ok(None);
+    }
+    if &magic[0..4] == b"fLaC" {
+        let audio_start = skip_flac_metadata(reader, start + 4)?;
+        Ok(Some(vec![(audio_start, file_size)]))
+    } else if &magic[4..8] == b"ftyp" {
+        mdat_ranges(reader, file_size).map(Some)
+    } else if magic[0] == 0xFF && magic[1] & 0xE0 == 0xE0 {
+        let end = end_without_trailing_tags(reader, start, file_size)?;
+        Ok(Some(vec![(start, end)]))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Every metadata block starts with a flag for the last block,
+/// its type (7 bit) and its length (24 bit).
+fn skip_flac_metadata<R: Read + Seek>(reader: &mut R, mut position: u64) -> io::Result<u64> {
+    let mut header = [0u8; 4];
+    loop {
+        reader.seek(SeekFrom::Start(position))?;
+        reader.read_exact(&mut header)?;
+        position += 4 + big_endian(&header[1..4]);
+        if header[0] & 0x80 != 0 {
+            return Ok(position);
+        }
+    }
+}
+
+/// The bodies of all top level mdat atoms.
+fn mdat_ranges<R: Read + Seek>(reader: &mut R, file_size: u64) -> io::Result<Vec<(u64, u64)>> {
+    let mut ranges = vec![];
+    let mut position = 0;
+    let mut header = [0u8; 16];
+    while position + 8 <= file_size {
+        reader.seek(SeekFrom::Start(position))?;
+        reader.read_exact(&mut header[0..8])?;
+        let (header_size, size) = match big_endian(&header[0..4]) {
+            0 => (8, file_size - position),
+            1 => {
+                reader.read_exact(&mut header[8..16])?;
+                (16, big_endian(&header[8..16]))
+            }
+            size => (8, size),
+        };
+        let end = match position.checked_add(size) {
+            Some(end) if size >= header_size => end,
+            _ => break,
+        };
+        if &header[4..8] == b"mdat" {
+            ranges.push((position + header_size, end.min(file_size)));
+        }
+        position = end;
+    }
+    Ok(ranges)
+}
+
+/// The end of the audio before an ID3v1 tag and an APE tag.
+fn end_without_trailing_tags<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    mut end: u64,
+) -> io::Result<u64> {
+    let mut tag = [0u8; 32];
+    if end >= start + 128 {
+        reader.seek(SeekFrom::Start(end - 128))?;
+        reader.read_exact(&mut tag[0..3])?;
+        if &tag[0..3] == b"TAG" {
+            end -= 128;
+        }
+    }
+    if end >= start + 32 {
+        reader.seek(SeekFrom::Start(end - 32))?;
+        reader.read_exact(&mut tag)?;
+        if &tag[0..8] == b"APETAGEX" {
+            // the size includes the footer, but not the optional header
+            let size = little_endian(&tag[12..16]);
+            let header = if little_endian(&tag[20..24]) & 0x8000_0000 != 0 {
+                32
+            } else {
+                0
+            };
+            end = end.saturating_sub(size + header).max(start);
+        }
+    }
+    Ok(end)
+}
+
+fn big_endian(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn little_endian(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn hash(data: Vec<u8>) -> Option<String> {
+        hash_audio_payload(&mut Cursor::new(data)).unwrap()
+    }
+
+    #[test]
+    fn mpeg_payload_without_tags() {
+        let audio = [0xFFu8, 0xFB, 0x90, 0x00, 1, 2, 3, 4].repeat(20);
+        let mut tagged = b"ID3\x03\x00\x00\x00\x00\x00\x05hello".to_vec();
+        tagged.extend_from_slice(&audio);
+        let mut id3v1 = b"TAG".to_vec();
+        id3v1.extend(vec![b'x'; 125]);
+        tagged.extend(id3v1);
+
+        assert!(hash(audio.clone()).is_some());
+        assert_eq!(hash(audio.clone()), hash(tagged));
+        let mut other_audio = audio;
+        other_audio[5] = 9;
+        assert_ne!(
+            hash(other_audio),
+            hash([0xFFu8, 0xFB, 0x90, 0x00, 1, 2, 3, 4].repeat(20))
+        );
+    }
+
+    #[test]
+    fn flac_payload_without_metadata() {
+        let flac = |comment: &[u8]| {
+            let mut data = b"fLaC".to_vec();
+            data.extend_from_slice(&[0x84, 0, 0, comment.len() as u8]);
+            data.extend_from_slice(comment);
+            data.extend_from_slice(&[0xFF, 0xF8, 1, 2, 3]);
+            data
+        };
+        assert_eq!(hash(flac(b"title=a")), hash(flac(b"title=something else")));
+    }
+
+    #[test]
+    fn mdat_ranges_of_broken_sizes() {
+        let mut data = vec![0, 0, 0, 12];
+        data.extend_from_slice(b"mdat\x01\x02\x03\x04");
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(b"free");
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        let file_size = data.len() as u64;
+        let ranges = mdat_ranges(&mut Cursor::new(data), file_size).unwrap();
+        assert_eq!(ranges, [(8, 12)]);
+    }
+
+    #[test]
+    fn unknown_payload() {
+        assert_eq!(hash(b"just some text".to_vec()), None);
+    }
+}
@@ -0,0 +1,110 @@
+//! Reads M3U/M3U8 playlists, which often come with a book and tell exactly
+//! which files belong to it and in which order.
+use super::cue;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A playlist with its entries resolved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Playlist {
+    pub path: PathBuf,
+    /// given by `#PLAYLIST:`, else the file name
+    pub title: String,
+    /// canonical paths of the existing entries, in playlist order
+    pub entries: Vec<PathBuf>,
+    /// entries pointing to missing files, as written
+    pub broken: Vec<String>,
+}
+
+impl Playlist {
+    /// Reads the playlist, m3u8 is UTF-8, m3u often Latin-1.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(path, &cue::decode(fs::read(path)?)))
+    }
+
+    /// Resolves relative entries against the folder of the playlist.
+    /// Streams (URLs) are no files of a book and left out.
+    pub fn parse(path: &Path, text: &str) -> Self {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut title = None;
+        let mut entries = vec![];
+        let mut broken = vec![];
+        for line in text.lines().map(str::trim) {
+            if let Some(directive) = line.strip_prefix('#') {
+                if let Some(playlist_title) = directive.strip_prefix("PLAYLIST:") {
+                    title = Some(playlist_title.trim().to_string());
+                }
+                continue;
+            }
+            if line.is_empty() || (line.contains("://") && !line.starts_with("file://")) {
+                continue;
+            }
+            let entry = line.trim_start_matches("file://");
+            // playlists written on windows
+            let entry = if cfg!(windows) {
+                entry.to_string()
+            } else {
+                entry.replace('\\', "/")
+            };
+            match dir.join(&entry).canonicalize() {
+                Ok(entry) if entry.is_file() => entries.push(entry),
+                _ => broken.push(line.to_string()),
+            }
+        }
+        Self {
+            path: path.to_path_buf(),
+            title: title.unwrap_or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }),
+            entries,
+            broken,
+        }
+    }
+
+    /// The folder the playlist is in, which is where its book is.
+    pub fn folder(&self) -> String {
+        self.path
+            .parent()
+            .and_then(|parent| parent.to_str())
+            .unwrap_or("")
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn resolve_entries() {
+        let dir = env::temp_dir()
+            .join("adbf_playlist_test")
+            .join("Animal Farm");
+        fs::create_dir_all(dir.join("cd1")).unwrap();
+        fs::write(dir.join("cd1").join("01.mp3"), b"").unwrap();
+        fs::write(dir.join("02.mp3"), b"").unwrap();
+        let absolute = dir.join("02.mp3").canonicalize().unwrap();
+        let text = format!(
+            "#EXTM3U\n#PLAYLIST: Animal Farm\n\
+             #EXTINF:123,George Orwell - Chapter I\ncd1\\01.mp3\n\
+             {}\nhttp://example.com/stream\n03.mp3\n",
+            absolute.display()
+        );
+        let playlist = Playlist::parse(&dir.join("book.m3u"), &text);
+        assert_eq!(playlist.title, "Animal Farm");
+        assert_eq!(
+            playlist.entries,
+            vec![
+                dir.join("cd1").join("01.mp3").canonicalize().unwrap(),
+                absolute
+            ]
+        );
+        assert_eq!(playlist.broken, vec!["03.mp3".to_string()]);
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
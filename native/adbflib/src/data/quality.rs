@@ -0,0 +1,194 @@
+//! The technical quality of the audio: codec, bitrate, sample rate, channels
+//! and the size of the files. Copies of the same book are ranked by it, the
+//! best one is the copy to keep.
+use super::audio_book::AudioBook;
+use std::{fmt, time::Duration};
+
+/// How the audio is encoded.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Mp1,
+    Mp2,
+    Mp3,
+    Aac,
+    Alac,
+    Flac,
+    Vorbis,
+    Opus,
+}
+
+impl Codec {
+    /// Lossless copies are better than any lossy one, whatever their bitrate.
+    pub fn is_lossless(&self) -> bool {
+        matches!(self, Codec::Alac | Codec::Flac)
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Codec::Mp1 => "mp1",
+            Codec::Mp2 => "mp2",
+            Codec::Mp3 => "mp3",
+            Codec::Aac => "aac",
+            Codec::Alac => "alac",
+            Codec::Flac => "flac",
+            Codec::Vorbis => "vorbis",
+            Codec::Opus => "opus",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The quality of a track, or of a whole book.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quality {
+    pub codec: Codec,
+    /// the average bitrate in kbit/s, 0 until known
+    pub bitrate: u32,
+    /// variable bitrate, if the stream tells
+    pub variable: Option<bool>,
+    /// in Hz
+    pub sample_rate: u32,
+    pub channels: u8,
+    /// bytes of the file (or of the archive entry)
+    pub size: u64,
+}
+
+impl Quality {
+    /// What the stream headers tell, the file size is added later.
+    pub fn new(
+        codec: Codec,
+        bitrate: u32,
+        variable: Option<bool>,
+        sample_rate: u32,
+        channels: u8,
+    ) -> Self {
+        Self {
+            codec,
+            bitrate,
+            variable,
+            sample_rate,
+            channels,
+            size: 0,
+        }
+    }
+
+    /// Adds the file size, and estimates the bitrate by it if the stream
+    /// did not tell (lossless streams never do).
+    pub fn complete(&mut self, size: u64, duration: Duration) {
+        self.size = size;
+        if self.bitrate == 0 {
+            self.bitrate = bitrate_of(size, duration);
+        }
+    }
+
+    /// Better copies rank higher: lossless first, then by bitrate,
+    /// sample rate and channels.
+    pub fn rank(&self) -> (bool, u32, u32, u8) {
+        (
+            self.codec.is_lossless(),
+            self.bitrate,
+            self.sample_rate,
+            self.channels,
+        )
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}kbit/s", self.codec, self.bitrate)?;
+        match self.variable {
+            Some(true) => write!(f, " VBR")?,
+            Some(false) => write!(f, " CBR")?,
+            None => (),
+        }
+        let channels = match self.channels {
+            1 => "mono".to_string(),
+            2 => "stereo".to_string(),
+            channels => format!("{} channels", channels),
+        };
+        write!(
+            f,
+            " {}Hz {} {:.1}MB",
+            self.sample_rate,
+            channels,
+            self.size as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+/// The average bitrate in kbit/s of that many bytes played that long.
+pub fn bitrate_of(bytes: u64, duration: Duration) -> u32 {
+    match duration.as_millis() as u64 {
+        0 => 0,
+        // bits per millisecond are kbit/s
+        millis => (bytes * 8 / millis) as u32,
+    }
+}
+
+/// The quality of a book is the one of its weakest track: lossy if any
+/// track is, the lowest sample rate and channels. The bitrate is the
+/// average over its duration, the size is the one of all files.
+pub fn of(audio_book: &AudioBook) -> Option<Quality> {
+    let qualities: Vec<(&Quality, Duration)> = audio_book
+        .tracks
+        .iter()
+        .filter_map(|track| {
+            track
+                .quality
+                .as_ref()
+                .map(|quality| (quality, track.duration))
+        })
+        .collect();
+    let (first, _) = qualities.first()?;
+    let mut book = **first;
+    for (quality, _) in &qualities {
+        if book.codec.is_lossless() && !quality.codec.is_lossless() {
+            book.codec = quality.codec;
+        }
+        book.variable = match (book.variable, quality.variable) {
+            (Some(own), Some(other)) => Some(own || other),
+            (Some(true), None) | (None, Some(true)) => Some(true),
+            _ => None,
+        };
+        book.sample_rate = book.sample_rate.min(quality.sample_rate);
+        book.channels = book.channels.min(quality.channels);
+    }
+    let duration: Duration = qualities.iter().map(|(_, duration)| *duration).sum();
+    let bits: u64 = qualities
+        .iter()
+        .map(|(quality, duration)| quality.bitrate as u64 * duration.as_millis() as u64)
+        .sum();
+    book.bitrate = match duration.as_millis() as u64 {
+        0 => first.bitrate,
+        millis => (bits / millis) as u32,
+    };
+    book.size = qualities.iter().map(|(quality, _)| quality.size).sum();
+    Some(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_copies() {
+        let mut mp3 = Quality::new(Codec::Mp3, 0, Some(true), 44100, 2);
+        mp3.complete(60 * 16000, Duration::from_secs(60));
+        assert_eq!(mp3.bitrate, 128);
+        assert_eq!(mp3.size, 960_000);
+        let mono = Quality::new(Codec::Mp3, 128, Some(false), 44100, 1);
+        let aac = Quality::new(Codec::Aac, 64, Some(false), 44100, 2);
+        let flac = Quality::new(Codec::Flac, 0, None, 22050, 1);
+
+        let mut copies = vec![aac, mono, flac, mp3];
+        copies.sort_by_key(|quality| std::cmp::Reverse(quality.rank()));
+        assert_eq!(copies, vec![flac, mp3, mono, aac]);
+        assert_eq!(
+            mp3.to_string(),
+            "mp3 128kbit/s VBR 44100Hz stereo 0.9MB".to_string()
+        );
+        assert_eq!(bitrate_of(1000, Duration::from_secs(0)), 0);
+    }
+}
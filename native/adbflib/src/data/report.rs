@@ -0,0 +1,209 @@
+//! The duplicate report tells which books are duplicates of which: books
+//! with exactly the same key, and books with similar keys. Books read by
+//! another narrator are reported as edition variants, not as duplicates.
+//! The books of a cluster are ranked by quality, the best copy first, and
+//! each other copy tells whether its chapters and its cover are the ones of
+//! the best copy.
+use super::{
+    audio_book::{self, AudioBook},
+    audio_info::AudioInfoKey,
+    cover::{self, Cover},
+    kind::BookKind,
+    quality::Quality,
+};
+use std::{cmp::Reverse, fmt, time::Duration};
+
+/// chapters may differ that much and still be the same
+static CHAPTER_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// How the books of a cluster match.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Match {
+    Exact,
+    Similar,
+    /// same (or similar) key, but another narrator
+    Edition,
+}
+
+/// A single file of a reported book.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReportedFile {
+    pub path: String,
+    pub duration: Duration,
+}
+
+/// A book as part of a duplicate cluster.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReportedBook {
+    pub folder: String,
+    pub album: Option<String>,
+    pub narrator: Option<String>,
+    pub duration: Duration,
+    pub files: Vec<ReportedFile>,
+    /// id of the cover, its image is served by the webui
+    pub cover: Option<String>,
+    pub kind: BookKind,
+    pub quality: Option<Quality>,
+    /// lengths of the chapters (or files), to compare them with other copies
+    pub chapters: Vec<Duration>,
+    /// the chapters are the ones of the best copy, none for the best copy
+    pub same_chapters: Option<bool>,
+    /// it shows the cover of the best copy, none if one has no cover
+    pub same_cover: Option<bool>,
+}
+
+impl ReportedBook {
+    fn of(audio_book: &AudioBook) -> Self {
+        Self {
+            folder: audio_book.folder.clone(),
+            album: audio_book
+                .tracks
+                .iter()
+                .find_map(|track| track.album.clone()),
+            narrator: audio_book.narrator().cloned(),
+            duration: audio_book.duration,
+            files: audio_book
+                .tracks
+                .iter()
+                .map(|track| ReportedFile {
+                    path: track.file_name.clone(),
+                    duration: track.duration,
+                })
+                .collect(),
+            cover: audio_book.cover().map(Cover::id),
+            kind: audio_book.kind(),
+            quality: audio_book.quality(),
+            chapters: audio_book.chapter_lengths(),
+            same_chapters: None,
+            same_cover: None,
+        }
+    }
+
+    /// Books of one folder, like the discs of a book, differ by their files.
+    fn is_of(&self, audio_book: &AudioBook) -> bool {
+        self.folder == audio_book.folder
+            && self.files.first().map(|file| &file.path)
+                == audio_book.tracks.first().map(|track| &track.file_name)
+    }
+}
+
+/// Books which are (probably) the same, the key is the one of the first book.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DuplicateCluster {
+    pub matching: Match,
+    pub key: String,
+    pub books: Vec<ReportedBook>,
+}
+
+impl DuplicateCluster {
+    /// The copy to keep is the best one, edition variants are all kept.
+    pub fn keep(&self) -> Option<&ReportedBook> {
+        match self.matching {
+            Match::Edition => None,
+            _ => self.books.first(),
+        }
+    }
+
+    /// Ranks the books by quality (unknown quality last), and compares
+    /// chapters and covers of the others with the best one.
+    fn rank(&mut self) {
+        self.books
+            .sort_by_key(|book| Reverse(book.quality.as_ref().map(Quality::rank)));
+        if let Some((best, others)) = self.books.split_first_mut() {
+            best.same_chapters = None;
+            best.same_cover = None;
+            for book in others {
+                book.same_cover = match (&best.cover, &book.cover) {
+                    (Some(best_cover), Some(cover)) => cover::is_same_id(best_cover, cover),
+                    _ => None,
+                };
+                book.same_chapters = Some(audio_book::same_chapters(
+                    &best.chapters,
+                    &book.chapters,
+                    CHAPTER_TOLERANCE,
+                ));
+            }
+        }
+    }
+}
+
+/// All duplicate clusters found.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DuplicateReport {
+    pub clusters: Vec<DuplicateCluster>,
+}
+
+impl DuplicateReport {
+    pub fn new() -> Self {
+        Self { clusters: vec![] }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clusters.is_empty()
+    }
+
+    /// Adds the books to the cluster of the key, books already in that
+    /// cluster are not added twice. The best copy comes first, see `rank`.
+    pub fn add(&mut self, matching: Match, key: &AudioInfoKey, audio_books: &[&AudioBook]) {
+        let position = self
+            .clusters
+            .iter()
+            .position(|cluster| cluster.matching == matching && &cluster.key == key.get());
+        let cluster = match position {
+            Some(position) => &mut self.clusters[position],
+            None => {
+                self.clusters.push(DuplicateCluster {
+                    matching,
+                    key: key.get().clone(),
+                    books: vec![],
+                });
+                self.clusters.last_mut().unwrap()
+            }
+        };
+        for audio_book in audio_books {
+            if !cluster.books.iter().any(|book| book.is_of(audio_book)) {
+                cluster.books.push(ReportedBook::of(audio_book));
+            }
+        }
+        cluster.rank();
+    }
+}
+
+impl fmt::Display for DuplicateReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for cluster in &self.clusters {
+            let matching = match cluster.matching {
+                Match::Exact => "same",
+                Match::Similar => "similar",
+                Match::Edition => "edition variant",
+            };
+            writeln!(f, "{} '{}':", matching, cluster.key)?;
+            let keep = cluster.keep().map(|book| &book.folder);
+            for book in &cluster.books {
+                let chapters = match book.same_chapters {
+                    Some(false) => ", other chapters",
+                    _ => "",
+                };
+                let cover = match book.same_cover {
+                    Some(false) => ", other cover",
+                    _ => "",
+                };
+                writeln!(
+                    f,
+                    "{} {} ({}, {}, {} files, {}s, {}{}{})",
+                    if keep == Some(&book.folder) { "*" } else { " " },
+                    book.folder,
+                    book.album.as_deref().unwrap_or("no album"),
+                    book.narrator.as_deref().unwrap_or("no narrator"),
+                    book.files.len(),
+                    book.duration.as_secs(),
+                    book.quality
+                        .map_or("unknown quality".to_string(), |quality| quality.to_string()),
+                    chapters,
+                    cover
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
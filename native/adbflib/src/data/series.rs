@@ -0,0 +1,165 @@
+//! Tells the series of a book and its volume in there, from the tags
+//! (movement, grouping, sidecars), the album, the folder names like
+//! "The Expanse/03 - Abaddon's Gate" and the titles. Volumes of a series
+//! have similar names, but are never duplicates of each other.
+use super::{audio_book::AudioBook, audio_info::AudioInfo};
+use regex::Regex;
+use std::path::Path;
+
+/// Numbered parts of a single book, "Chapter 1 - Intro" is no series.
+static PART_WORDS: [&str; 10] = [
+    "chapter",
+    "kapitel",
+    "chapitre",
+    "capítulo",
+    "part",
+    "teil",
+    "cd",
+    "disc",
+    "disk",
+    "track",
+];
+
+lazy_static! {
+    /// "The Expanse #3", "The Expanse, Book 3", "Die drei ??? Folge 12"
+    static ref MARKED_VOLUME: Regex = Regex::new(
+        r"(?i)^(.*?)[\s,:(\[-]*(?:#|\b(?:book|band|bd\.|folge|vol\.|volume|tome|tomo|libro)\s*)(\d+(?:\.\d+)?)\b"
+    )
+    .unwrap();
+    /// "The Expanse 03 - Abaddon's Gate"
+    static ref NUMBERED_TITLE: Regex =
+        Regex::new(r"^(.*?\D)\s+(\d{1,3}(?:\.\d+)?)\s*[-–:]\s+\S").unwrap();
+    /// "03 - Abaddon's Gate", a folder inside the folder of its series
+    static ref VOLUME_FIRST: Regex = Regex::new(r"^(\d{1,3}(?:\.\d+)?)\s*[-–.]\s+\S").unwrap();
+}
+
+/// Series name (if it tells) and volume of a name with a volume in it.
+pub fn parse(text: &str) -> Option<(Option<String>, String)> {
+    let captures = MARKED_VOLUME.captures(text).or_else(|| {
+        NUMBERED_TITLE
+            .captures(text)
+            .filter(|captures| !is_part(&captures[1]))
+    })?;
+    let name = captures[1].trim_end_matches(|c: char| c.is_whitespace() || ",:([-–".contains(c));
+    let name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+    Some((name, normalize_volume(&captures[2])))
+}
+
+/// The name ends with a word numbering the parts of a book.
+fn is_part(name: &str) -> bool {
+    name.split_whitespace()
+        .last()
+        .map(str::to_lowercase)
+        .is_some_and(|word| PART_WORDS.contains(&word.as_str()))
+}
+
+/// "03" and "3" are the same volume, so are "2.50" and "2.5".
+pub fn normalize_volume(volume: &str) -> String {
+    let volume = volume.split('/').next().unwrap_or("").trim();
+    match volume.parse::<f64>() {
+        Ok(number) => number.to_string(),
+        Err(_) => volume.to_string(),
+    }
+}
+
+/// Series and volume of the book, each from the first source telling it:
+/// the tags, the grouping, the album, the folders, then the titles.
+pub fn of(audio_book: &AudioBook) -> (Option<String>, Option<String>) {
+    let tracks = &audio_book.tracks;
+    let mut series: Option<String> = None;
+    let mut volume: Option<String> = None;
+    let mut take = |name: Option<String>, number: Option<String>| {
+        if series.is_none() {
+            series = name;
+        }
+        if volume.is_none() {
+            volume = number;
+        }
+    };
+    // a series name may come with its volume ("The Expanse #3"), or without
+    let named = |text: &String| match parse(text) {
+        Some((name, number)) => (name, Some(number)),
+        None => (Some(text.trim().to_string()), None),
+    };
+    let first = |field: fn(&AudioInfo) -> Option<&String>| {
+        tracks
+            .iter()
+            .filter_map(field)
+            .find(|text| !text.trim().is_empty())
+    };
+
+    if let Some(text) = first(|track| track.series.as_ref()) {
+        let (name, number) = named(text);
+        take(name, number);
+    }
+    take(None, first(|track| track.volume.as_ref()).cloned());
+    if let Some(text) = first(|track| track.grouping.as_ref()) {
+        let (name, number) = named(text);
+        take(name, number);
+    }
+    if let Some((name, number)) = first(|track| track.album.as_ref()).and_then(|album| parse(album))
+    {
+        take(name, Some(number));
+    }
+    let folder = Path::new(&audio_book.folder);
+    let folder_name = |path: &Path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map(String::from)
+    };
+    if let Some(name) = folder_name(folder) {
+        match (parse(&name), VOLUME_FIRST.captures(&name)) {
+            (Some((name, number)), _) => take(name, Some(number)),
+            // the folder above is the one of the series
+            (None, Some(captures)) => take(
+                folder.parent().and_then(folder_name),
+                Some(normalize_volume(&captures[1])),
+            ),
+            (None, None) => (),
+        }
+    }
+    // titles are chapters mostly, only if all of them tell the same
+    let titles: Vec<_> = tracks.iter().map(|track| parse(&track.title)).collect();
+    if let Some(Some((name, number))) = titles.first() {
+        if titles.iter().all(|title| title == &titles[0]) {
+            take(name.clone(), Some(number.clone()));
+        }
+    }
+    (series, volume)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_series_and_volume() {
+        let series = |name: &str| Some(name.to_string());
+        assert_eq!(
+            parse("The Expanse 03 - Abaddon's Gate"),
+            Some((series("The Expanse"), "3".to_string()))
+        );
+        assert_eq!(
+            parse("The Expanse #3"),
+            Some((series("The Expanse"), "3".to_string()))
+        );
+        assert_eq!(
+            parse("The Wheel of Time, Book 2.50"),
+            Some((series("The Wheel of Time"), "2.5".to_string()))
+        );
+        assert_eq!(
+            parse("Die drei ??? (Folge 125)"),
+            Some((series("Die drei ???"), "125".to_string()))
+        );
+        assert_eq!(parse("Band 4"), Some((None, "4".to_string())));
+        // numbers which are no volumes
+        assert_eq!(parse("1984"), None);
+        assert_eq!(parse("Catch 22"), None);
+        assert_eq!(parse("Fahrenheit 451"), None);
+        // parts of a book
+        assert_eq!(parse("Chapter 1 - Intro"), None);
+        assert_eq!(parse("Kapitel 3 - Der Anfang"), None);
+        assert_eq!(parse("Animal Farm CD 2 - Track 4"), None);
+        assert_eq!(normalize_volume("3/9"), "3");
+    }
+}
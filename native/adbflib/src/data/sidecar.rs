@@ -0,0 +1,294 @@
+//! Reads the metadata files book managers put into the folder of a book:
+//! `metadata.opf` (Calibre, OverDrive), `metadata.json` (Audiobookshelf),
+//! `desc.txt` and `reader.txt`. They complete the tags of all its files.
+use super::{cue, tag_readers::CommonAudioInfo};
+use regex::Regex;
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+static OPF_FILE: &str = "metadata.opf";
+static JSON_FILE: &str = "metadata.json";
+static DESCRIPTION_FILE: &str = "desc.txt";
+static NARRATOR_FILE: &str = "reader.txt";
+
+lazy_static! {
+    /// an element with its attributes and its text
+    static ref OPF_ELEMENT: Regex =
+        Regex::new(r"(?s)<dc:(\w+)([^>]*)>(.*?)</dc:\w+>").unwrap();
+    static ref OPF_META: Regex = Regex::new(r"<meta\s[^>]*>").unwrap();
+    static ref ATTRIBUTE: Regex = Regex::new(r#"([\w:]+)\s*=\s*"([^"]*)""#).unwrap();
+    static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+}
+
+/// What the metadata files of a folder tell about its book.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sidecar {
+    /// the metadata files read
+    pub files: Vec<PathBuf>,
+    pub author: Option<String>,
+    pub title: Option<String>,
+    pub series: Option<String>,
+    pub narrator: Option<String>,
+    pub isbn: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Sidecar {
+    /// Reads the metadata files among the files of a folder. Where they
+    /// disagree, OPF goes before JSON before the text files.
+    pub fn of(paths: &[PathBuf]) -> Self {
+        let mut sidecar = Self::default();
+        let find = |name: &str| {
+            paths.iter().find(|path| {
+                path.file_name()
+                    .and_then(|file_name| file_name.to_str())
+                    .is_some_and(|file_name| file_name.eq_ignore_ascii_case(name))
+            })
+        };
+        let read = |path: &PathBuf| match fs::read(path) {
+            Ok(bytes) => Some(cue::decode(bytes)),
+            Err(e) => {
+                warn!("could not read {:?}: {}", path, e);
+                None
+            }
+        };
+        if let Some(path) = find(OPF_FILE) {
+            if let Some(text) = read(path) {
+                sidecar.merge(Self::parse_opf(&text), path);
+            }
+        }
+        if let Some(path) = find(JSON_FILE) {
+            if let Some(text) = read(path) {
+                match Self::parse_json(&text) {
+                    Ok(json) => sidecar.merge(json, path),
+                    Err(e) => warn!("could not read {:?}: {}", path, e),
+                }
+            }
+        }
+        if let Some(path) = find(DESCRIPTION_FILE) {
+            if let Some(text) = read(path) {
+                let description = Self {
+                    description: non_empty(&text),
+                    ..Self::default()
+                };
+                sidecar.merge(description, path);
+            }
+        }
+        if let Some(path) = find(NARRATOR_FILE) {
+            if let Some(text) = read(path) {
+                let narrator = Self {
+                    narrator: non_empty(&text),
+                    ..Self::default()
+                };
+                sidecar.merge(narrator, path);
+            }
+        }
+        sidecar
+    }
+
+    /// Fills in what tags (and CUE sheets) leave empty.
+    pub fn complete(&self, audio_info: &mut CommonAudioInfo) {
+        let fill = |field: &mut String, value: &Option<String>| {
+            if field.is_empty() {
+                if let Some(value) = value {
+                    *field = value.clone();
+                }
+            }
+        };
+        fill(&mut audio_info.artist, &self.author);
+        fill(&mut audio_info.title, &self.title);
+        let fill_option = |field: &mut Option<String>, value: &Option<String>| {
+            if field.as_ref().is_none_or(String::is_empty) && value.is_some() {
+                *field = value.clone();
+            }
+        };
+        fill_option(&mut audio_info.album, &self.title);
+        fill_option(&mut audio_info.album_artist, &self.author);
+        fill_option(&mut audio_info.series, &self.series);
+        fill_option(&mut audio_info.narrator, &self.narrator);
+        fill_option(&mut audio_info.isbn, &self.isbn);
+        fill_option(&mut audio_info.description, &self.description);
+    }
+
+    /// Takes what is still unknown from the other one, read from the file.
+    fn merge(&mut self, other: Self, file: &Path) {
+        self.files.push(file.to_path_buf());
+        self.author = self.author.take().or(other.author);
+        self.title = self.title.take().or(other.title);
+        self.series = self.series.take().or(other.series);
+        self.narrator = self.narrator.take().or(other.narrator);
+        self.isbn = self.isbn.take().or(other.isbn);
+        self.description = self.description.take().or(other.description);
+    }
+
+    /// Dublin Core elements and Calibre meta elements of an OPF package.
+    fn parse_opf(text: &str) -> Self {
+        let mut sidecar = Self::default();
+        for element in OPF_ELEMENT.captures_iter(text) {
+            let attributes = attributes(&element[2]);
+            let role = attribute(&attributes, "role");
+            let value = || non_empty(&plain_text(&element[3]));
+            let field = match (&element[1], role) {
+                ("title", _) => &mut sidecar.title,
+                ("creator", Some("nrt")) | ("contributor", Some("nrt")) => &mut sidecar.narrator,
+                ("creator", Some("aut")) | ("creator", None) => &mut sidecar.author,
+                ("identifier", _)
+                    if attribute(&attributes, "scheme")
+                        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("isbn")) =>
+                {
+                    &mut sidecar.isbn
+                }
+                ("description", _) => &mut sidecar.description,
+                _ => continue,
+            };
+            if field.is_none() {
+                *field = value();
+            }
+        }
+        for meta in OPF_META.find_iter(text) {
+            let attributes = attributes(meta.as_str());
+            if attribute(&attributes, "name") == Some("calibre:series") {
+                sidecar.series = attribute(&attributes, "content").and_then(non_empty);
+            }
+        }
+        sidecar
+    }
+
+    /// The book as described by Audiobookshelf.
+    fn parse_json(text: &str) -> serde_json::Result<Self> {
+        let json: Value = serde_json::from_str(text)?;
+        let text = |key: &str| json[key].as_str().and_then(non_empty);
+        // lists of names, series with their volume like "The Expanse #3"
+        let joined = |key: &str| match &json[key] {
+            Value::Array(values) => {
+                let values: Vec<&str> = values.iter().filter_map(Value::as_str).collect();
+                non_empty(&values.join(", "))
+            }
+            value => value.as_str().and_then(non_empty),
+        };
+        Ok(Self {
+            author: joined("authors").or_else(|| text("author")),
+            title: text("title"),
+            series: joined("series"),
+            narrator: joined("narrators").or_else(|| text("narrator")),
+            isbn: text("isbn"),
+            description: text("description")
+                .and_then(|description| non_empty(&plain_text(&description))),
+            ..Self::default()
+        })
+    }
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// Descriptions are HTML, in OPF files even escaped once more.
+fn plain_text(html: &str) -> String {
+    let text = unescape(&TAG.replace_all(html, ""));
+    if text.contains('<') {
+        unescape(&TAG.replace_all(&text, ""))
+    } else {
+        text
+    }
+}
+
+/// Attributes without their namespace (`opf:role` is `role`).
+fn attributes(text: &str) -> Vec<(String, String)> {
+    ATTRIBUTE
+        .captures_iter(text)
+        .map(|attribute| {
+            let name = attribute[1].rsplit(':').next().unwrap_or_default();
+            (name.to_string(), unescape(&attribute[2]))
+        })
+        .collect()
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(attribute, _)| attribute == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// The predefined entities and numeric character references.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let character = entity.and_then(|(name, _)| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => name
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| name.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        });
+        match (character, entity) {
+            (Some(character), Some((_, end))) => {
+                unescaped.push(character);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_opf_and_json() {
+        let opf = r#"<?xml version="1.0" encoding="utf-8"?>
+            <package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+              <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+                <dc:title>Abaddon&apos;s Gate</dc:title>
+                <dc:creator opf:file-as="Corey, James S. A." opf:role="aut">James S. A. Corey</dc:creator>
+                <dc:creator opf:role="nrt">Jefferson Mays</dc:creator>
+                <dc:identifier opf:scheme="ISBN">9780316129077</dc:identifier>
+                <dc:identifier opf:scheme="calibre">1234</dc:identifier>
+                <dc:description>&lt;p&gt;Book three &amp;amp; more&lt;/p&gt;</dc:description>
+                <meta name="calibre:series" content="The Expanse"/>
+                <meta name="calibre:series_index" content="3.0"/>
+              </metadata>
+            </package>"#;
+        let sidecar = Sidecar::parse_opf(opf);
+        assert_eq!(sidecar.title.as_deref(), Some("Abaddon's Gate"));
+        assert_eq!(sidecar.author.as_deref(), Some("James S. A. Corey"));
+        assert_eq!(sidecar.narrator.as_deref(), Some("Jefferson Mays"));
+        assert_eq!(sidecar.isbn.as_deref(), Some("9780316129077"));
+        assert_eq!(sidecar.series.as_deref(), Some("The Expanse"));
+        assert_eq!(sidecar.description.as_deref(), Some("Book three & more"));
+
+        let json = r#"{"title": "Abaddon's Gate", "authors": ["James S. A. Corey"],
+            "narrators": ["Jefferson Mays"], "series": ["The Expanse #3"],
+            "isbn": null, "description": "<p>Book three</p>"}"#;
+        let sidecar = Sidecar::parse_json(json).unwrap();
+        assert_eq!(sidecar.author.as_deref(), Some("James S. A. Corey"));
+        assert_eq!(sidecar.series.as_deref(), Some("The Expanse #3"));
+        assert_eq!(sidecar.isbn, None);
+        assert_eq!(sidecar.description.as_deref(), Some("Book three"));
+    }
+}
@@ -1,9 +1,25 @@
 /// Module with interface to different tag readers
+use super::{
+    archive::StoredEntry,
+    audio_info::Chapter,
+    id3v2, mp4,
+    mpeg::{self, MpegStream},
+    ogg,
+    quality::{Codec, Quality},
+    series,
+};
 use id3::Tag as id3tag;
-use metaflac::{block::Block, Tag as flactag};
+use metaflac::{
+    block::{Block, PictureType},
+    Tag as flactag,
+};
 use mp3_metadata;
 use mp4ameta::Tag as mp4tag;
-use std::{fs::File, io::BufReader, time::Duration};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+    time::Duration,
+};
 
 #[allow(dead_code)]
 #[derive(Clone)]
@@ -19,23 +35,231 @@ pub struct CommonAudioInfo {
     pub total_discs: Option<u16>,
     pub total_tracks: Option<u16>,
     pub year: Option<i32>,
+    pub chapters: Option<Vec<Chapter>>,
+    pub series: Option<String>,
+    /// the volume inside the series
+    pub volume: Option<String>,
+    /// often the series, or series and volume
+    pub grouping: Option<String>,
+    pub narrator: Option<String>,
+    /// audiobooks keep the narrator there, if not in its own field
+    pub composer: Option<String>,
+    pub isbn: Option<String>,
+    pub description: Option<String>,
+    /// the embedded cover image
+    pub cover: Option<Vec<u8>>,
+    /// as told by the stream, without the file size
+    pub quality: Option<Quality>,
+}
+
+impl CommonAudioInfo {
+    /// Nothing known but what the stream tells.
+    fn untagged(duration: Duration, quality: Option<Quality>) -> Self {
+        Self {
+            title: String::new(),
+            artist: String::new(),
+            duration,
+            album: None,
+            track: None,
+            album_artist: None,
+            genre: None,
+            disc: None,
+            total_discs: None,
+            total_tracks: None,
+            year: None,
+            chapters: None,
+            series: None,
+            volume: None,
+            grouping: None,
+            narrator: None,
+            composer: None,
+            isbn: None,
+            description: None,
+            cover: None,
+            quality,
+        }
+    }
+}
+
+/// What the tag readers read: a file, or an entry of an archive held in memory.
+pub enum AudioSource {
+    File(BufReader<File>),
+    Memory(Cursor<Vec<u8>>),
+    /// an uncompressed entry of an archive
+    Stored(StoredEntry),
+}
+
+impl Read for AudioSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            AudioSource::File(file) => file.read(buf),
+            AudioSource::Memory(memory) => memory.read(buf),
+            AudioSource::Stored(entry) => entry.read(buf),
+        }
+    }
+}
+
+impl BufRead for AudioSource {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            AudioSource::File(file) => file.fill_buf(),
+            AudioSource::Memory(memory) => memory.fill_buf(),
+            AudioSource::Stored(entry) => entry.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            AudioSource::File(file) => file.consume(amt),
+            AudioSource::Memory(memory) => memory.consume(amt),
+            AudioSource::Stored(entry) => entry.consume(amt),
+        }
+    }
+}
+
+impl Seek for AudioSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            AudioSource::File(file) => file.seek(pos),
+            AudioSource::Memory(memory) => memory.seek(pos),
+            AudioSource::Stored(entry) => entry.seek(pos),
+        }
+    }
 }
 
 /// Trait to ensure same calls
 pub trait TagReader<'a> {
-    fn read_tag_from(&self, file: &mut BufReader<File>) -> Result<CommonAudioInfo, String>;
+    fn read_tag_from(&self, file: &mut AudioSource) -> Result<CommonAudioInfo, String>;
     fn known_suffixes(&self) -> Vec<&'a str>;
+    /// name to map mime types to this reader
+    fn name(&self) -> &'a str;
+}
+
+lazy_static! {
+    /// All tag readers, in the order they are tried if no mapping tells otherwise
+    pub static ref TAG_READERS: [Box<dyn TagReader<'static> + Sync>; 5] = [
+        Box::new(MP4TagReader),
+        Box::new(FlacTagReader),
+        Box::new(OggTagReader),
+        Box::new(ID3TagReader),
+        Box::new(MP3TagReader),
+    ];
+}
+
+/// Every reader starts at the beginning, even if a reader before failed in the middle.
+fn rewind(file_buffer: &mut AudioSource) -> Result<(), String> {
+    file_buffer
+        .seek(SeekFrom::Start(0))
+        .map(|_| ())
+        .map_err(|e| format!("{:?}", e))
+}
+
+/// Only what the stream tells, for files without (readable) tags: the
+/// duration and quality of a flac or mpeg stream.
+pub fn read_untagged(file_buffer: &mut AudioSource) -> Option<CommonAudioInfo> {
+    rewind(file_buffer).ok()?;
+    if let Ok(tag) = flactag::read_from(&mut *file_buffer) {
+        return tag.blocks().find_map(|block| match block {
+            Block::StreamInfo(stream_info) => Some(CommonAudioInfo::untagged(
+                flac_duration(stream_info.total_samples, stream_info.sample_rate),
+                flac_quality(&tag),
+            )),
+            _ => None,
+        });
+    }
+    rewind(file_buffer).ok()?;
+    mpeg_stream(file_buffer)
+        .map(|stream| CommonAudioInfo::untagged(stream.duration, Some(stream.quality)))
+}
+
+/// Reads only the embedded cover, of whatever format the file is.
+pub fn read_cover(file_buffer: &mut AudioSource) -> Option<Vec<u8>> {
+    id3v2::read_cover(file_buffer)
+        .ok()
+        .flatten()
+        .or_else(|| {
+            mp4::read_moov(file_buffer)
+                .ok()
+                .and_then(|moov| mp4::read_cover(&moov))
+        })
+        .or_else(|| {
+            rewind(file_buffer).ok()?;
+            flactag::read_from(file_buffer)
+                .ok()
+                .and_then(|tag| flac_cover(&tag))
+        })
+}
+
+/// The front cover, or the first picture if none is marked as front cover.
+fn flac_cover(tag: &flactag) -> Option<Vec<u8>> {
+    let pictures: Vec<_> = tag
+        .blocks()
+        .filter_map(|block| match block {
+            Block::Picture(picture) => Some(picture),
+            _ => None,
+        })
+        .collect();
+    pictures
+        .iter()
+        .find(|picture| matches!(picture.picture_type, PictureType::CoverFront))
+        .or_else(|| pictures.first())
+        .map(|picture| picture.data.clone())
+}
+
+/// The duration of a flac stream, given by the STREAMINFO block.
+fn flac_duration(total_samples: u64, sample_rate: u32) -> Duration {
+    if sample_rate == 0 {
+        Duration::from_secs(0)
+    } else {
+        Duration::from_millis(total_samples * 1000 / sample_rate as u64)
+    }
+}
+
+/// The quality told by the STREAMINFO block, flac streams have no bitrate.
+fn flac_quality(tag: &flactag) -> Option<Quality> {
+    tag.blocks().find_map(|block| match block {
+        Block::StreamInfo(stream_info) => Some(Quality::new(
+            Codec::Flac,
+            0,
+            None,
+            stream_info.sample_rate,
+            stream_info.num_channels,
+        )),
+        _ => None,
+    })
+}
+
+/// The duration and quality of the mpeg stream, if the stream is readable.
+fn mpeg_stream(file_buffer: &mut AudioSource) -> Option<MpegStream> {
+    mpeg::read_stream(file_buffer)
+        .map_err(|e| trace!("no mpeg stream: {}", e))
+        .ok()
 }
 
 pub struct MP4TagReader;
 impl<'a> TagReader<'a> for MP4TagReader {
-    fn read_tag_from(&self, file_buffer: &mut BufReader<File>) -> Result<CommonAudioInfo, String> {
-        match mp4tag::read_from(file_buffer.get_mut()) {
+    fn read_tag_from(&self, file_buffer: &mut AudioSource) -> Result<CommonAudioInfo, String> {
+        rewind(file_buffer)?;
+        match mp4tag::read_from(&mut *file_buffer) {
             Ok(tag) => {
                 // year needs extra treatment
                 let year = tag
                     .year()
                     .map_or(None, |good_string| good_string.parse::<i32>().ok());
+                // chapters are not part of the tag, mp4ameta ignores them
+                let moov = mp4::read_moov(file_buffer)
+                    .map_err(|e| trace!("no mp4 moov: {}", e))
+                    .ok();
+                let chapters = moov
+                    .as_ref()
+                    .and_then(|moov| {
+                        mp4::read_chapters(file_buffer, moov)
+                            .map_err(|e| trace!("no mp4 chapters: {}", e))
+                            .ok()
+                    })
+                    .filter(|chapters| !chapters.is_empty());
+                let cover = moov.as_deref().and_then(mp4::read_cover);
+                let quality = moov.as_deref().and_then(mp4::read_quality);
 
                 let info = CommonAudioInfo {
                     title: tag.title().unwrap_or("").to_string(),
@@ -49,6 +273,16 @@ impl<'a> TagReader<'a> for MP4TagReader {
                     total_discs: None, // no supported
                     total_tracks: tag.total_tracks(),
                     year,
+                    chapters,
+                    series: tag.movement().map(|op| op.to_string()),
+                    volume: tag.movement_index().map(|nr| nr.to_string()),
+                    grouping: tag.grouping().map(|op| op.to_string()),
+                    narrator: None,
+                    composer: tag.composer().map(|op| op.to_string()),
+                    isbn: None,
+                    description: None,
+                    cover,
+                    quality,
                 };
                 Ok(info)
             }
@@ -57,20 +291,50 @@ impl<'a> TagReader<'a> for MP4TagReader {
     }
 
     fn known_suffixes(&self) -> Vec<&'a str> {
-        vec!["mp4"]
+        vec!["mp4", "x-m4a", "x-m4b", "mp4a-latm"]
+    }
+    fn name(&self) -> &'a str {
+        "mp4"
     }
 }
 
 pub struct ID3TagReader;
 impl<'a> TagReader<'a> for ID3TagReader {
-    fn read_tag_from(&self, file_buffer: &mut BufReader<File>) -> Result<CommonAudioInfo, String> {
-        match id3tag::read_from(file_buffer.get_mut()) {
+    fn read_tag_from(&self, file_buffer: &mut AudioSource) -> Result<CommonAudioInfo, String> {
+        rewind(file_buffer)?;
+        match id3tag::read_from(&mut *file_buffer) {
             Ok(tag) => {
+                // the stream knows its length better than TLEN (in ms), if present at all
+                let stream = mpeg_stream(file_buffer);
+                let duration = match stream.as_ref().map(|stream| stream.duration) {
+                    Some(stream_duration) if stream_duration > Duration::from_secs(0) => {
+                        stream_duration
+                    }
+                    _ => Duration::from_millis(tag.duration().unwrap_or(0) as u64),
+                };
+                let quality = stream.map(|stream| stream.quality);
+                // one file books come with CHAP frames
+                let chapters = id3v2::read_chapters(file_buffer)
+                    .map_err(|e| trace!("no id3 chapters: {}", e))
+                    .ok()
+                    .filter(|chapters| !chapters.is_empty());
+                let cover = id3v2::read_cover(file_buffer)
+                    .map_err(|e| trace!("no id3 cover: {}", e))
+                    .unwrap_or(None);
+                let text_frame = |id: &str| {
+                    tag.get(id)
+                        .and_then(|frame| frame.content().text())
+                        .map(|text| text.to_string())
+                };
+                let narrator = tag
+                    .extended_texts()
+                    .find(|extended| extended.description.eq_ignore_ascii_case("NARRATOR"))
+                    .map(|extended| extended.value.clone());
                 // write into common audio info that can be analyzed
                 let info = CommonAudioInfo {
                     title: tag.title().unwrap_or("").to_string(),
                     artist: tag.artist().unwrap_or("").to_string(),
-                    duration: Duration::from_secs(tag.duration().unwrap_or(0) as u64),
+                    duration,
                     album: tag.album().map(|s| s.to_string()),
                     track: tag.track().and_then(|v| Some(v as u16)),
                     album_artist: tag.album_artist().map(|st| st.to_string()),
@@ -79,6 +343,16 @@ impl<'a> TagReader<'a> for ID3TagReader {
                     total_discs: tag.total_discs().and_then(|v| Some(v as u16)),
                     total_tracks: tag.total_tracks().and_then(|v| Some(v as u16)),
                     year: tag.year(),
+                    chapters,
+                    series: text_frame("MVNM"),
+                    volume: text_frame("MVIN").map(|nr| series::normalize_volume(&nr)),
+                    grouping: text_frame("TIT1"),
+                    narrator,
+                    composer: text_frame("TCOM"),
+                    isbn: None,
+                    description: None,
+                    cover,
+                    quality,
                 };
                 Ok(info)
             }
@@ -89,13 +363,28 @@ impl<'a> TagReader<'a> for ID3TagReader {
     fn known_suffixes(&self) -> Vec<&'a str> {
         vec!["mpeg"]
     }
+    fn name(&self) -> &'a str {
+        "id3"
+    }
 }
 
 pub struct FlacTagReader;
 impl<'a> TagReader<'a> for FlacTagReader {
-    fn read_tag_from(&self, file_buffer: &mut BufReader<File>) -> Result<CommonAudioInfo, String> {
+    fn read_tag_from(&self, file_buffer: &mut AudioSource) -> Result<CommonAudioInfo, String> {
+        rewind(file_buffer)?;
         match flactag::read_from(file_buffer) {
             Ok(tag_block) => {
+                // STREAMINFO is always the first block, but don't count on it
+                let duration = tag_block
+                    .blocks()
+                    .find_map(|block| match block {
+                        Block::StreamInfo(stream_info) => Some(flac_duration(
+                            stream_info.total_samples,
+                            stream_info.sample_rate,
+                        )),
+                        _ => None,
+                    })
+                    .unwrap_or(Duration::from_secs(0));
                 // try the first block
                 let mut res_info = Err("vorbis comment block not found".to_string());
                 for good_tag_block in tag_block.blocks() {
@@ -115,8 +404,7 @@ impl<'a> TagReader<'a> for FlacTagReader {
                             res_info = Ok(CommonAudioInfo {
                                 title: take_first_or_empty(tag.title()),
                                 artist: take_first_or_empty(tag.artist()),
-                                // todo: duration somewhere else
-                                duration: Duration::from_secs(0),
+                                duration,
                                 album: take_first_or_option(tag.album()),
                                 track: tag.track().and_then(|v| Some(v as u16)),
                                 album_artist: take_first_or_option(tag.album_artist()),
@@ -126,6 +414,18 @@ impl<'a> TagReader<'a> for FlacTagReader {
                                 total_discs: Some(0), // tag.total_discs(),
                                 total_tracks: tag.total_tracks().and_then(|v| Some(v as u16)),
                                 year: Some(0), //tag.year(),
+                                chapters: None,
+                                series: take_first_or_option(tag.get("MOVEMENTNAME")),
+                                volume: take_first_or_option(tag.get("MOVEMENT"))
+                                    .map(|nr| series::normalize_volume(&nr)),
+                                grouping: take_first_or_option(tag.get("GROUPING")),
+                                narrator: take_first_or_option(tag.get("NARRATOR"))
+                                    .or_else(|| take_first_or_option(tag.get("PERFORMER"))),
+                                composer: take_first_or_option(tag.get("COMPOSER")),
+                                isbn: None,
+                                description: None,
+                                cover: flac_cover(&tag_block),
+                                quality: flac_quality(&tag_block),
                             });
                         }
                         _ => (),
@@ -138,22 +438,36 @@ impl<'a> TagReader<'a> for FlacTagReader {
     }
 
     fn known_suffixes(&self) -> Vec<&'a str> {
-        vec!["flac", "x-vorbis+ogg"]
+        vec!["flac"]
+    }
+    fn name(&self) -> &'a str {
+        "flac"
     }
 }
 
 pub struct MP3TagReader;
 impl<'a> TagReader<'a> for MP3TagReader {
-    fn read_tag_from(&self, file_buffer: &mut BufReader<File>) -> Result<CommonAudioInfo, String> {
-        match mp3_metadata::read_from_slice(file_buffer.buffer()) {
+    fn read_tag_from(&self, file_buffer: &mut AudioSource) -> Result<CommonAudioInfo, String> {
+        rewind(file_buffer)?;
+        let metadata = file_buffer
+            .fill_buf()
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|buffer| {
+                mp3_metadata::read_from_slice(buffer).map_err(|e| format!("{:?}", e))
+            });
+        match metadata {
             Ok(metadata) => {
                 match metadata.tag {
                     Some(tag) => {
+                        let stream = mpeg_stream(file_buffer);
+                        let duration = stream
+                            .as_ref()
+                            .map_or(Duration::from_secs(0), |stream| stream.duration);
                         // write into common audio info that can be analyzed
                         let info = CommonAudioInfo {
                             title: tag.title,
                             artist: tag.artist,
-                            duration: Duration::from_secs(0),
+                            duration,
                             album: Some(tag.album),
                             track: None,
                             album_artist: None,
@@ -162,17 +476,92 @@ impl<'a> TagReader<'a> for MP3TagReader {
                             total_discs: None,
                             total_tracks: None,
                             year: Some(tag.year as i32),
+                            chapters: None,
+                            series: None,
+                            volume: None,
+                            grouping: None,
+                            narrator: None,
+                            composer: None,
+                            isbn: None,
+                            description: None,
+                            cover: None,
+                            quality: stream.map(|stream| stream.quality),
                         };
                         Ok(info)
                     }
                     None => Err("no audio tag found".to_string()),
                 }
             }
-            Err(e) => Err(format!("{:?}", e)),
+            Err(e) => Err(e),
         }
     }
 
     fn known_suffixes(&self) -> Vec<&'a str> {
         vec!["mpeg", "mp3"]
     }
+    fn name(&self) -> &'a str {
+        "mp3"
+    }
+}
+
+pub struct OggTagReader;
+impl<'a> TagReader<'a> for OggTagReader {
+    fn read_tag_from(&self, file_buffer: &mut AudioSource) -> Result<CommonAudioInfo, String> {
+        match ogg::read_stream(file_buffer) {
+            Ok(stream) => {
+                // numbers may come as "3/12"
+                let number = |key: &str| {
+                    stream
+                        .first(key)
+                        .and_then(|value| {
+                            value
+                                .split('/')
+                                .next()
+                                .map(|nr| nr.trim().parse::<u16>().ok())
+                        })
+                        .unwrap_or(None)
+                };
+                let info = CommonAudioInfo {
+                    title: stream.first("TITLE").unwrap_or_default(),
+                    artist: stream.first("ARTIST").unwrap_or_default(),
+                    duration: stream.duration,
+                    album: stream.first("ALBUM"),
+                    track: number("TRACKNUMBER"),
+                    album_artist: stream
+                        .first("ALBUMARTIST")
+                        .or_else(|| stream.first("ALBUM ARTIST")),
+                    genre: stream.first("GENRE"),
+                    disc: number("DISCNUMBER"),
+                    total_discs: number("DISCTOTAL").or_else(|| number("TOTALDISCS")),
+                    total_tracks: number("TRACKTOTAL").or_else(|| number("TOTALTRACKS")),
+                    year: stream
+                        .first("DATE")
+                        .and_then(|date| date.get(0..4).and_then(|year| year.parse::<i32>().ok())),
+                    chapters: None,
+                    series: stream.first("MOVEMENTNAME"),
+                    volume: stream
+                        .first("MOVEMENT")
+                        .map(|nr| series::normalize_volume(&nr)),
+                    grouping: stream.first("GROUPING"),
+                    narrator: stream
+                        .first("NARRATOR")
+                        .or_else(|| stream.first("PERFORMER")),
+                    composer: stream.first("COMPOSER"),
+                    isbn: None,
+                    description: None,
+                    cover: None,
+                    quality: Some(stream.quality),
+                };
+                Ok(info)
+            }
+            Err(e) => Err(format!("{:?}", e)),
+        }
+    }
+
+    fn known_suffixes(&self) -> Vec<&'a str> {
+        vec!["ogg", "x-vorbis+ogg", "opus", "x-opus+ogg"]
+    }
+    fn name(&self) -> &'a str {
+        "ogg"
+    }
 }
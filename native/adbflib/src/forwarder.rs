@@ -10,6 +10,8 @@ use crate::{
             IFCollectionOutputData,
             IPC::{self, DoneSearching},
         },
+        report::DuplicateReport,
+        IFInternalCollectionOutputData,
     },
     net::subs::peer_representation::peer_to_hash_string,
     shared,
@@ -31,12 +33,45 @@ lazy_static! {
     static ref NET_RUNTIME: Mutex<(Receiver<UiUpdateMsg>,Sender<IPC>)> = Mutex::new(create_net_runtime());
     /// A static mutable data collection, its inside to be sent to Dart via FFI
     static ref NET_UI : Mutex<UIList> = Mutex::new(UIList { cnt: Vec::new() });
+    /// The paths and the duplicate report of the last search, which needs no search again
+    static ref LAST_SEARCH: Mutex<Option<(Vec<String>, DuplicateReport)>> = Mutex::new(None);
 }
 
 /// Return the number of audio files found for now
 pub fn ffi_file_count_good(input_path: Vec<String>) -> u32 {
     // todo: return other values of IFInternalCollectionOutputData
+    let (output_data_return_handle, _) = search_without_ui(input_path);
 
+    let (_, ipc_sender) = &mut *NET_RUNTIME.lock().unwrap();
+    let sending = IFCollectionOutputData {
+        nr_searched_files: output_data_return_handle.nr_searched_files,
+        nr_found_songs: output_data_return_handle.nr_found_songs,
+        size_of_data_in_kb: 0,
+        nr_internal_duplicates: output_data_return_handle.nr_internal_duplicates,
+    };
+    ipc_sender.send(DoneSearching(sending)).unwrap();
+
+    // scope and block trickery for lifetime and mutability
+    output_data_return_handle.nr_found_songs
+}
+
+/// Return the json of the duplicate report of the given paths, the paths
+/// are only searched if they were not the ones of the last search
+pub fn ffi_duplicate_report_as_json(input_path: Vec<String>) -> String {
+    let last_search = match &*LAST_SEARCH.lock().unwrap() {
+        Some((searched_path, report)) if *searched_path == input_path => Some(report.clone()),
+        _ => None,
+    };
+    let report = match last_search {
+        Some(report) => report,
+        None => search_without_ui(input_path).1,
+    };
+    serde_json::to_string(&report).unwrap()
+}
+
+/// Runs the search on the given paths and returns its results, the
+/// duplicate report is also kept as the one of the last search
+fn search_without_ui(input_path: Vec<String>) -> (IFInternalCollectionOutputData, DuplicateReport) {
     // prepare data
     let cleaned_paths = SearchPath::new(&input_path);
     let search_path = Arc::new(Mutex::new(cleaned_paths));
@@ -47,24 +82,15 @@ pub fn ffi_file_count_good(input_path: Vec<String>) -> u32 {
 
     // set up data and run search
     let collection_protected = Arc::new(Mutex::new(Collection::new()));
-    let output_data_return_handle = shared::collection_search(
-        collection_protected,
+    let output_data = shared::collection_search(
+        collection_protected.clone(),
         search_path,
         synced_to_ui_messages,
         has_ui,
     );
-
-    let (_, ipc_sender) = &mut *NET_RUNTIME.lock().unwrap();
-    let sending = IFCollectionOutputData {
-        nr_searched_files: output_data_return_handle.nr_searched_files,
-        nr_found_songs: output_data_return_handle.nr_found_songs,
-        size_of_data_in_kb: 0,
-        nr_internal_duplicates: output_data_return_handle.nr_internal_duplicates,
-    };
-    ipc_sender.send(DoneSearching(sending)).unwrap();
-
-    // scope and block trickery for lifetime and mutability
-    output_data_return_handle.nr_found_songs
+    let report = collection_protected.lock().unwrap().duplicate_report();
+    *LAST_SEARCH.lock().unwrap() = Some((input_path, report.clone()));
+    (output_data, report)
 }
 
 /// Return the json of peer uis which holds a vector of UIListInner
@@ -106,6 +132,7 @@ pub async fn ffi_ui_messages_as_json() -> String {
                         break;
                     }
                 }
+                UiUpdateMsg::DuplicatesFound(_) => {}
                 UiUpdateMsg::StopUI => unreachable!(),
             }
         }
@@ -45,6 +45,11 @@ pub async fn file_count_good(input_path: Vec<String>) -> Result<u32, AdbflibErro
     Ok(forwarder::ffi_file_count_good(input_path))
 }
 
+/// the library interface for returning the duplicates found as json
+pub async fn duplicate_report(input_path: Vec<String>) -> Result<String, AdbflibError> {
+    Ok(forwarder::ffi_duplicate_report_as_json(input_path))
+}
+
 /// the library interface for returning own peer id
 pub fn get_own_peer() -> u64 {
     net::subs::peer_representation::peer_to_hash(&net::subs::key_keeper::get_p2p_server_id())
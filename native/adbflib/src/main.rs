@@ -19,6 +19,7 @@ use adbfbinlib::{
     data::{
         collection::Collection,
         ipc::{IFCollectionOutputData, IPC},
+        report::DuplicateReport,
     },
     net::subs::key_keeper,
     shared,
@@ -38,7 +39,7 @@ use std::{
 /// the adbflib, which is closely connected.
 fn main() -> io::Result<()> {
     // get start values from the input parser!!!
-    let (ui_paths, has_webui, has_net, keep_alive, open_browser, web_port, has_ui) =
+    let (ui_paths, has_webui, has_net, keep_alive, open_browser, web_port, has_ui, scan_options) =
         command_line::get_start_values();
 
     // read into paths
@@ -173,7 +174,7 @@ fn main() -> io::Result<()> {
         let synced_to_ui_messages = tx_from_collector_to_ui.clone();
 
         // set up data
-        let collection_protected = SArc::new(SMutex::new(Collection::new()));
+        let collection_protected = SArc::new(SMutex::new(Collection::with_options(scan_options)));
 
         // search parallelly
         let output_data = shared::collection_search(
@@ -216,6 +217,20 @@ fn main() -> io::Result<()> {
                     .unwrap_or_else(|e| error!("use one: {}", e));
             }
         }
+        let duplicate_report = collection_protected
+            .lock()
+            .map(|locked_collection| locked_collection.duplicate_report())
+            .unwrap_or_else(|_| {
+                error!("locking collection didn't work here!");
+                DuplicateReport::new()
+            });
+        if has_ui {
+            tx_col
+                .send(UiUpdateMsg::DuplicatesFound(duplicate_report))
+                .unwrap_or_else(|e| error!("use one: {}", e));
+        } else if !duplicate_report.is_empty() {
+            println!("Duplicates found:\n{}", duplicate_report);
+        }
         if !has_ui {
             collection_protected
                 .lock()
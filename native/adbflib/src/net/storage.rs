@@ -1,7 +1,8 @@
 //! wraps up "net storage" which is implementing the kademlia functionality.
 use super::{
     super::data::{
-        audio_info::{AudioInfo, AudioInfoKey},
+        audio_book::AudioBook,
+        audio_info::AudioInfoKey,
         ipc::{IFCollectionOutputData, IPC},
     },
     subs::peer_representation::{self, PeerRepresentation},
@@ -22,7 +23,7 @@ use libp2p::{
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum MkadKeys {
     KeyForPeerFinished(PeerRepresentation),
-    SingleAudioRecord(AudioInfoKey),
+    AudioBookRecord(AudioInfoKey),
 }
 
 /// Capsulates NetStorage activity internally
@@ -82,16 +83,16 @@ impl NetStorage {
                     }
                     Some(bincode::serialize(&value_to_send).unwrap())
                 }
-                IPC::PublishSingleAudioDataRecord(audio_key, audio_info) => {
-                    // a single audio data
-                    bin_key = Self::key_writer(MkadKeys::SingleAudioRecord(audio_key));
+                IPC::PublishAudioBookRecord(audio_key, audio_book) => {
+                    // a single audio book
+                    bin_key = Self::key_writer(MkadKeys::AudioBookRecord(audio_key));
                     if let Some(already_audio_record) = kademlia.store_mut().get(&bin_key) {
-                        let already_audio_data: Result<AudioInfo, bincode::Error> =
+                        let already_audio_data: Result<AudioBook, bincode::Error> =
                             bincode::deserialize(already_audio_record.value.as_ref());
                         if let Ok(found_and_deserializable) = already_audio_data {
                             info!(
                                 "This record was already found somewhere else, and put as {}!",
-                                found_and_deserializable.file_name
+                                found_and_deserializable.folder
                             );
                         } else {
                             info!("This record was already there and not even de-serializable!");
@@ -99,7 +100,7 @@ impl NetStorage {
                         None
                     } else {
                         // that is new and should be put
-                        Some(bincode::serialize(&audio_info).unwrap())
+                        Some(bincode::serialize(&audio_book).unwrap())
                     }
                 }
             };
@@ -162,8 +163,8 @@ impl NetStorage {
                                     "Successfully put record key KeyForPeerFinished for peer {:?}!",
                                     peer_representation::peer_hash_to_string(&peer_hash)
                                 ),
-                                MkadKeys::SingleAudioRecord(_audio_info) => {
-                                    info!("Successfully put record key SingleAudioRecord!")
+                                MkadKeys::AudioBookRecord(_audio_key) => {
+                                    info!("Successfully put record key AudioBookRecord!")
                                 }
                             },
                             Err(_) => {
@@ -196,8 +197,8 @@ impl NetStorage {
                     // todo: continue here
                     info!("key for peer finished of '{}' retrieved!", peer_hash);
                 }
-                MkadKeys::SingleAudioRecord(audio_key) => {
-                    info!("new audio data with key '{}' retrieved!", &audio_key.get());
+                MkadKeys::AudioBookRecord(audio_key) => {
+                    info!("new audio book with key '{}' retrieved!", &audio_key.get());
                 }
             }
         } else {
@@ -30,9 +30,14 @@ pub fn collection_search(
     let output_data_handle = Arc::new(Mutex::new(output_data));
     let output_data_handle2 = output_data_handle.clone();
 
-    let handle_container = Arc::new(Mutex::new(Container::new()));
+    let key_metric = collection_handler.lock().unwrap().key_metric();
+    let handle_container = Arc::new(Mutex::new(Container::with_metric(key_metric)));
 
     let current_search_path = search_path.lock().unwrap().read();
+
+    // files that did not change since the last run are taken from cache
+    collection_handler.lock().unwrap().load_cache();
+
     // start the parallel search threads with rayon, each path its own
     current_search_path
         .par_iter()
@@ -56,6 +61,11 @@ pub fn collection_search(
                 locker.nr_searched_files += single_path_collection_data.nr_searched_files;
             }
         });
+    collection_handler
+        .lock()
+        .unwrap()
+        .store_cache(&current_search_path);
+
     let out = &*output_data_handle2.lock().unwrap();
     out.clone()
 }